@@ -1,20 +1,71 @@
 use criterion::{criterion_group, criterion_main, Criterion};
+use sudoku_solver_and_generator::logic::board::{Board, Position};
 use sudoku_solver_and_generator::logic::game::Game;
 use sudoku_solver_and_generator::logic::solver::{generate, Solver}; // Adjust the path as necessary
 
+const HARD_9: &str =
+    "4.....8.5.3..........7......2.....6.....8.4......1.......6.3.7.5..2.....1.4......";
+
+const HARD_16: &str = "..f...5b.ge...1.......2...73.59.g.9.3..dfc.2.a48.....e.....52..f.1.a.7..g.c..b..7.ce..da......63..46..9e...7..a2...d....68.....4...5a348.79...c...63..cf.1....7..8b2.6.7.....fd.9...db..c..6318.....e..6..5...3..d...279..4.a.......c.8.7e..5.....e......agc.6b7";
+
+fn parse(puzzle: &str, side: usize, sub_rows: usize, sub_cols: usize) -> Game {
+    let mut game = Game::new(side, sub_rows, sub_cols).unwrap();
+    let mut board = Board::new(side);
+    for (i, c) in puzzle.chars().enumerate() {
+        let value = c.to_digit(36).unwrap_or(0) as usize;
+        board.set_value(
+            Position::new(i / side + 1, i % side + 1, side).unwrap(),
+            value,
+        );
+    }
+    game.set_board(board);
+    game
+}
+
 fn benchmark_solve(c: &mut Criterion) {
-    let g = Game::new(9, 3, 3);
+    let g = Game::new(9, 3, 3).unwrap();
     let puzzle = generate(g).unwrap();
     let mut solver = Solver::new(puzzle.clone(), true);
     c.bench_function("solve randomly", |b| b.iter(|| solver.solve()));
 }
 
 fn benchmark_solve_deterministic(c: &mut Criterion) {
-    let g = Game::new(9, 3, 3);
+    let g = Game::new(9, 3, 3).unwrap();
     let puzzle = generate(g).unwrap();
     let mut solver = Solver::new(puzzle.clone(), false);
     c.bench_function("solve deterministically", |b| b.iter(|| solver.solve()));
 }
 
-criterion_group!(benches, benchmark_solve, benchmark_solve_deterministic);
-criterion_main!(benches);
\ No newline at end of file
+fn benchmark_solve_hard(c: &mut Criterion) {
+    let puzzle = parse(HARD_9, 9, 3, 3);
+    c.bench_function("solve hard 9x9", |b| {
+        b.iter(|| Solver::new(puzzle.clone(), false).solve())
+    });
+
+    let puzzle = parse(HARD_16, 16, 4, 4);
+    let mut group = c.benchmark_group("16x16");
+    group.sample_size(10);
+    group.bench_function("solve hard 16x16", |b| {
+        b.iter(|| Solver::new(puzzle.clone(), false).solve())
+    });
+    group.finish();
+}
+
+fn benchmark_solve_25(c: &mut Criterion) {
+    let game = Game::new(25, 5, 5).unwrap();
+    let mut group = c.benchmark_group("25x25");
+    group.sample_size(10);
+    group.bench_function("solve empty 25x25", |b| {
+        b.iter(|| Solver::new(game.clone(), false).solve())
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    benchmark_solve,
+    benchmark_solve_deterministic,
+    benchmark_solve_hard,
+    benchmark_solve_25
+);
+criterion_main!(benches);
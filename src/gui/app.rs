@@ -1,26 +1,262 @@
 use super::field_button::*;
 use super::rule_button::*;
-use crate::logic::board::Position;
+use crate::logic::board::{Board, Corner, Outside, Position};
+use crate::logic::deduction::{LogicalSolver, Step};
 use crate::logic::game;
-use crate::logic::rules::{PermutationRule, RelationRule, Rule, SumRule};
+use crate::logic::rules::{
+    ArrowRule, CageRule, Diagonal, DigitFilter, ExpressionRule, GlobalRule, KropkiColor,
+    KropkiRule, LineKind, LineRule, LittleKillerRule, PermutationRule, QuadRule, RelationRule,
+    RestrictionRule, Rule, SandwichRule, SumRule, ThermometerRule, XVMark, XVRule,
+};
 use crate::logic::solver::Solver;
-use crate::logic::solver::generate;
+use crate::logic::solver::{generate, generate_with_difficulty, rate, Difficulty};
 use crate::{choose_color, for_pos};
 use gtk::glib::Propagation;
 use gtk::prelude::{
-    BoxExt, ButtonExt, DialogExt, EntryBufferExtManual, EntryExt, GridExt, GtkWindowExt,
-    OrientableExt, PopoverExt, WidgetExt,
+    BoxExt, ButtonExt, CheckButtonExt, DialogExt, EntryBufferExtManual, EntryExt, GridExt,
+    GtkWindowExt, OrientableExt, PopoverExt, WidgetExt,
 };
-use relm4::factory::FactoryVecDeque;
+use relm4::factory::{FactoryVecDeque, FactoryVecDequeGuard};
 use relm4::{ComponentParts, ComponentSender, RelmWidgetExt, SimpleComponent};
 
 const COLOR_LIST: [&str; 10] = [
     "white", "grey", "red", "green", "purple", "orange", "pink", "brown", "black", "yellow",
 ];
 
-const N: usize = 9;
-const R: usize = 3;
-const C: usize = 3;
+/// Shape of the board the window opens with; every later size comes from the current game.
+const START_SIDE: usize = 9;
+const START_BOX_ROWS: usize = 3;
+const START_BOX_COLS: usize = 3;
+const HINT_COLOR: usize = 9;
+const REGION_COLORS: [usize; 4] = [7, 8, 10, 11];
+
+fn restore_colors(
+    fields: &mut FactoryVecDequeGuard<Field>,
+    positions: &[Position],
+    side: usize,
+    colors: &[usize],
+) {
+    for pos in positions {
+        let index = pos.index(side);
+        fields.send(index, FieldMsg::ChangeColor(colors[index]));
+    }
+}
+
+fn fill_fields(fields: &mut FactoryVecDequeGuard<Field>, game: &game::Game, colors: &[usize]) {
+    let (side, board) = (game.get_side(), game.board());
+    fields.clear();
+    for_pos!(side, |pos: Position| {
+        fields.push_back((colors[pos.index(side)], side, board.is_blocked(pos)));
+    });
+}
+
+fn refresh_markers(fields: &mut FactoryVecDequeGuard<Field>, game: &game::Game) {
+    let side = game.get_side();
+    let mut markers = vec![None; side * side];
+
+    for rule in game.rules() {
+        if let Rule::Restriction(r) = rule {
+            let marker = match r.get_filter() {
+                DigitFilter::Even => Some("even"),
+                DigitFilter::Odd => Some("odd"),
+                _ => None,
+            };
+            for pos in &r.positions {
+                let cell = &mut markers[pos.index(side)];
+                *cell = cell.or(marker);
+            }
+        }
+    }
+
+    for (index, marker) in markers.into_iter().enumerate() {
+        fields.send(index, FieldMsg::SetMarker(marker));
+    }
+}
+
+/// Colors the regions so that neighbouring ones differ, as far as the palette allows.
+fn region_colors(regions: &[Vec<usize>], board: &Board) -> Vec<usize> {
+    let side = regions.len();
+    let count = regions
+        .iter()
+        .flatten()
+        .max()
+        .map_or(0, |&region| region + 1);
+    let region_of = |pos: Position| regions[pos.row() - 1][pos.col() - 1];
+
+    let mut neighbours = vec![vec![]; count];
+    for pos in board.cells() {
+        for other in [pos.offset(0, 1, side), pos.offset(1, 0, side)]
+            .into_iter()
+            .flatten()
+            .filter(|&other| board.contains(other))
+        {
+            let (a, b) = (region_of(pos), region_of(other));
+            if a != b {
+                neighbours[a].push(b);
+                neighbours[b].push(a);
+            }
+        }
+    }
+
+    let mut palette_index = vec![0; count];
+    for region in 0..count {
+        let taken: Vec<usize> = neighbours[region]
+            .iter()
+            .filter(|&&other| other < region)
+            .map(|&other| palette_index[other])
+            .collect();
+        palette_index[region] = (0..REGION_COLORS.len())
+            .find(|color| !taken.contains(color))
+            .unwrap_or(region % REGION_COLORS.len());
+    }
+
+    let mut colors = vec![0; side * side];
+    for_pos!(side, |pos: Position| {
+        colors[pos.index(side)] = REGION_COLORS[palette_index[region_of(pos)]];
+    });
+    colors
+}
+
+fn arrow_label(circle: usize) -> String {
+    match circle {
+        1 => String::from("Arrow"),
+        _ => format!("Arrow ({} in circle)", circle),
+    }
+}
+
+/// Writes the clues of outside rules into the frame around the fields
+/// and puts a circle with the quad digits over every corner with a quad.
+fn refresh_clues(grid: &gtk::Grid, labels: &mut Vec<gtk::Label>, game: &game::Game) {
+    for label in labels.drain(..) {
+        grid.remove(&label);
+    }
+
+    let mut clues: Vec<(Outside, String)> = vec![];
+    for rule in game.rules() {
+        let (clue, text) = match rule {
+            Rule::LittleKiller(r) => (
+                r.get_clue(),
+                format!("{}{}", r.get_sum(), r.get_direction()),
+            ),
+            Rule::Sandwich(r) => (r.get_clue(), r.get_sum().to_string()),
+            _ => continue,
+        };
+        match clues.iter_mut().find(|(existing, _)| *existing == clue) {
+            Some((_, existing)) => *existing = format!("{} {}", existing, text),
+            None => clues.push((clue, text)),
+        }
+    }
+
+    for (clue, text) in clues {
+        let label = gtk::Label::new(Some(&text));
+        grid.attach(&label, clue.col() as i32, clue.row() as i32, 1, 1);
+        labels.push(label);
+    }
+
+    for rule in game.rules() {
+        if let Rule::Quad(r) = rule {
+            let text = r
+                .get_digits()
+                .iter()
+                .map(|d| d.to_string())
+                .collect::<Vec<_>>();
+            let label = gtk::Label::new(Some(&text.join(" ")));
+            label.set_css_classes(&["quad"]);
+            label.set_halign(gtk::Align::Center);
+            label.set_valign(gtk::Align::Center);
+            label.set_can_target(false);
+
+            let corner = r.get_corner();
+            grid.attach(&label, corner.col() as i32, corner.row() as i32, 2, 2);
+            labels.push(label);
+        }
+    }
+}
+
+fn frame_clue(row: &str, col: &str, side: usize) -> Result<Outside, String> {
+    match (row.parse(), col.parse()) {
+        (Ok(row), Ok(col)) => Outside::new(row, col, side),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        format!(
+            "clue should lie on the frame, row or column 0 or {}",
+            side + 1
+        )
+    })
+}
+
+/// Rejects rules that would cover cells outside every grid of a multi-grid puzzle.
+fn avoid_blocked(positions: &[Position], board: &Board) -> Result<(), String> {
+    match positions.iter().find(|&&pos| board.is_blocked(pos)) {
+        Some(pos) => Err(format!("cell {:?} is blocked", pos)),
+        None => Ok(()),
+    }
+}
+
+fn little_killer(text: &str, board: &Board, index: usize) -> Result<LittleKillerRule, String> {
+    let side = board.get_side();
+    let parts: Vec<&str> = text.split_whitespace().collect();
+    let [sum, row, col, direction] = parts[..] else {
+        return Err(String::from("expected: sum row col direction"));
+    };
+
+    let sum = sum.parse::<usize>().map_err(|e| e.to_string())?;
+    let clue = frame_clue(row, col, side)?;
+    let direction = direction.parse::<Diagonal>()?;
+
+    let rule = LittleKillerRule::new(clue, direction, sum, side, index);
+    if rule.positions.is_empty() {
+        return Err(String::from("the clue does not point into the grid"));
+    }
+    avoid_blocked(&rule.positions, board)?;
+    Ok(rule)
+}
+
+fn sandwich(text: &str, board: &Board, index: usize) -> Result<SandwichRule, String> {
+    let side = board.get_side();
+    let parts: Vec<&str> = text.split_whitespace().collect();
+    let [sum, row, col] = parts[..] else {
+        return Err(String::from("expected: sum row col"));
+    };
+
+    let sum = sum.parse::<usize>().map_err(|e| e.to_string())?;
+    let rule = SandwichRule::new(frame_clue(row, col, side)?, sum, side, index);
+    if rule.positions.is_empty() {
+        return Err(String::from(
+            "the clue should stand beside a row or a column",
+        ));
+    }
+    avoid_blocked(&rule.positions, board)?;
+    Ok(rule)
+}
+
+fn quad(text: &str, board: &Board, index: usize) -> Result<QuadRule, String> {
+    let (side, digits_up_to) = (board.get_side(), board.get_digits());
+    let numbers = text
+        .split_whitespace()
+        .map(|part| part.parse::<usize>())
+        .collect::<Result<Vec<usize>, _>>()
+        .map_err(|e| e.to_string())?;
+    let [row, col, ref digits @ ..] = numbers[..] else {
+        return Err(String::from("expected: row col digits"));
+    };
+
+    let corner = Corner::new(row, col, side).ok_or_else(|| {
+        format!(
+            "the top-left cell should lie in rows and columns 1 to {}",
+            side - 1
+        )
+    })?;
+    if digits.is_empty()
+        || digits.len() > 4
+        || digits.iter().any(|d| !(1..=digits_up_to).contains(d))
+    {
+        return Err(format!("list 1 to 4 digits from 1 to {}", digits_up_to));
+    }
+    avoid_blocked(&corner.cells(), board)?;
+    Ok(QuadRule::new(corner, digits.to_vec(), index))
+}
 
 fn popup(text: &str) {
     let dialog = gtk::MessageDialog::new(
@@ -42,10 +278,19 @@ pub struct App {
     global_value: usize,
     rule_active: usize,
     game: game::Game,
+    region_colors: Vec<usize>,
+    painting: Option<Vec<Vec<usize>>>,
+    clues: Vec<gtk::Label>,
     finished: usize,
     planning: bool,
     show_rules: bool,
     hints: bool,
+    difficulty: Option<Difficulty>,
+    /// Id of the puzzle generated in the background, cleared when the game is replaced meanwhile.
+    generating: Option<usize>,
+    generations: usize,
+    next_hint: Option<Step>,
+    eliminated: Vec<(Position, usize)>,
 }
 
 #[derive(Debug)]
@@ -61,9 +306,16 @@ pub enum AppMsg {
     Help,
     Hints,
     Generate,
+    Generated(usize, Option<(game::Game, usize)>),
     Wrong,
     Load(String),
     Save(String),
+    NewGame(String),
+    SetDifficulty(String),
+    NextHint,
+    ApplyHint,
+    SetGlobalRule(GlobalRule, bool),
+    TogglePainting,
 }
 
 #[relm4::component(pub)]
@@ -88,7 +340,13 @@ impl SimpleComponent for App {
                     #[name(value_label)]
                     gtk::Label {
                         #[watch]
-                        set_label: &format!("Value: {}. Planning? {}. Click 'h' to show help", model.global_value, model.planning),
+                        set_label: &format!(
+                            "Value: {}. Planning? {}. {}{}Click 'h' to show help",
+                            model.global_value,
+                            model.planning,
+                            if model.painting.is_some() { "Painting regions. " } else { "" },
+                            if model.generating.is_some() { "Generating... " } else { "" },
+                        ),
                     },
 
                     gtk::Box {
@@ -134,7 +392,186 @@ impl SimpleComponent for App {
                                             gtk::Button {
                                                 set_label: "Relation: ",
                                                 connect_clicked => AppMsg::AddRule(2, 0.to_string()),
-                                            }
+                                            },
+
+                                            gtk::Box {
+                                                set_orientation: gtk::Orientation::Horizontal,
+                                                gtk::Label {
+                                                    set_label: "Cage (sum optional): ",
+                                                },
+                                                gtk::Entry {
+                                                    connect_activate[sender] => move |entry| {
+                                                        let buffer = entry.buffer();
+                                                        sender.input(AppMsg::AddRule(3, buffer.text().into()));
+                                                        buffer.delete_text(0, None);
+                                                    }
+                                                },
+                                            },
+
+                                            gtk::Button {
+                                                set_label: "Thermometer",
+                                                connect_clicked => AppMsg::AddRule(4, 0.to_string()),
+                                            },
+
+                                            gtk::Box {
+                                                set_orientation: gtk::Orientation::Horizontal,
+                                                gtk::Label {
+                                                    set_label: "Arrow (circle cells, default 1): ",
+                                                },
+                                                gtk::Entry {
+                                                    connect_activate[sender] => move |entry| {
+                                                        let buffer = entry.buffer();
+                                                        sender.input(AppMsg::AddRule(5, buffer.text().into()));
+                                                        buffer.delete_text(0, None);
+                                                    }
+                                                },
+                                            },
+
+                                            gtk::Button {
+                                                set_label: "Kropki white",
+                                                connect_clicked => AppMsg::AddRule(6, String::from("white")),
+                                            },
+
+                                            gtk::Button {
+                                                set_label: "Kropki black",
+                                                connect_clicked => AppMsg::AddRule(6, String::from("black")),
+                                            },
+
+                                            gtk::CheckButton {
+                                                set_label: Some("Kropki negative constraint"),
+                                                #[watch]
+                                                set_active: model.game.has_global_rule(GlobalRule::KropkiNegative),
+                                                connect_toggled[sender] => move |button| {
+                                                    sender.input(AppMsg::SetGlobalRule(GlobalRule::KropkiNegative, button.is_active()));
+                                                },
+                                            },
+
+                                            gtk::Button {
+                                                set_label: "XV: X",
+                                                connect_clicked => AppMsg::AddRule(7, String::from("x")),
+                                            },
+
+                                            gtk::Button {
+                                                set_label: "XV: V",
+                                                connect_clicked => AppMsg::AddRule(7, String::from("v")),
+                                            },
+
+                                            gtk::CheckButton {
+                                                set_label: Some("XV negative constraint"),
+                                                #[watch]
+                                                set_active: model.game.has_global_rule(GlobalRule::XVNegative),
+                                                connect_toggled[sender] => move |button| {
+                                                    sender.input(AppMsg::SetGlobalRule(GlobalRule::XVNegative, button.is_active()));
+                                                },
+                                            },
+
+                                            gtk::Box {
+                                                set_orientation: gtk::Orientation::Horizontal,
+                                                gtk::Label {
+                                                    set_label: "Digits (even/odd/low/high or a list): ",
+                                                },
+                                                gtk::Entry {
+                                                    connect_activate[sender] => move |entry| {
+                                                        let buffer = entry.buffer();
+                                                        sender.input(AppMsg::AddRule(8, buffer.text().into()));
+                                                        buffer.delete_text(0, None);
+                                                    }
+                                                },
+                                            },
+
+                                            gtk::Box {
+                                                set_orientation: gtk::Orientation::Horizontal,
+                                                gtk::Label {
+                                                    set_label: "Little killer (sum row col ul/ur/dl/dr): ",
+                                                },
+                                                gtk::Entry {
+                                                    connect_activate[sender] => move |entry| {
+                                                        let buffer = entry.buffer();
+                                                        sender.input(AppMsg::AddRule(9, buffer.text().into()));
+                                                        buffer.delete_text(0, None);
+                                                    }
+                                                },
+                                            },
+
+                                            gtk::Box {
+                                                set_orientation: gtk::Orientation::Horizontal,
+                                                gtk::Label {
+                                                    set_label: "Sandwich (sum row col): ",
+                                                },
+                                                gtk::Entry {
+                                                    connect_activate[sender] => move |entry| {
+                                                        let buffer = entry.buffer();
+                                                        sender.input(AppMsg::AddRule(10, buffer.text().into()));
+                                                        buffer.delete_text(0, None);
+                                                    }
+                                                },
+                                            },
+
+                                            gtk::Button {
+                                                set_label: "Line: palindrome",
+                                                connect_clicked => AppMsg::AddRule(11, String::from("palindrome")),
+                                            },
+
+                                            gtk::Button {
+                                                set_label: "Line: renban",
+                                                connect_clicked => AppMsg::AddRule(11, String::from("renban")),
+                                            },
+
+                                            gtk::Button {
+                                                set_label: "Line: whispers",
+                                                connect_clicked => AppMsg::AddRule(11, String::from("whispers")),
+                                            },
+
+                                            gtk::Button {
+                                                set_label: "Line: region sum",
+                                                connect_clicked => AppMsg::AddRule(11, String::from("region sum")),
+                                            },
+
+                                            gtk::Box {
+                                                set_orientation: gtk::Orientation::Horizontal,
+                                                gtk::Label {
+                                                    set_label: "Quad (top-left row col, digits): ",
+                                                },
+                                                gtk::Entry {
+                                                    connect_activate[sender] => move |entry| {
+                                                        let buffer = entry.buffer();
+                                                        sender.input(AppMsg::AddRule(12, buffer.text().into()));
+                                                        buffer.delete_text(0, None);
+                                                    }
+                                                },
+                                            },
+
+                                            gtk::Box {
+                                                set_orientation: gtk::Orientation::Horizontal,
+                                                gtk::Label {
+                                                    set_label: "Expression (e.g. r1c1 + r1c2 == 2 * r3c3): ",
+                                                },
+                                                gtk::Entry {
+                                                    connect_activate[sender] => move |entry| {
+                                                        let buffer = entry.buffer();
+                                                        sender.input(AppMsg::AddRule(13, buffer.text().into()));
+                                                        buffer.delete_text(0, None);
+                                                    }
+                                                },
+                                            },
+
+                                            gtk::CheckButton {
+                                                set_label: Some("Anti-knight"),
+                                                #[watch]
+                                                set_active: model.game.has_global_rule(GlobalRule::AntiKnight),
+                                                connect_toggled[sender] => move |button| {
+                                                    sender.input(AppMsg::SetGlobalRule(GlobalRule::AntiKnight, button.is_active()));
+                                                },
+                                            },
+
+                                            gtk::CheckButton {
+                                                set_label: Some("Anti-king"),
+                                                #[watch]
+                                                set_active: model.game.has_global_rule(GlobalRule::AntiKing),
+                                                connect_toggled[sender] => move |button| {
+                                                    sender.input(AppMsg::SetGlobalRule(GlobalRule::AntiKing, button.is_active()));
+                                                },
+                                            },
                                         },
                                     },
                                 },
@@ -157,6 +594,58 @@ impl SimpleComponent for App {
                             set_css_classes: choose_color!(model.finished),
                         },
 
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            gtk::Label {
+                                set_label: "Value: ",
+                            },
+                            gtk::Entry {
+                                set_css_classes: &["white"],
+                                connect_activate[sender] => move |entry| {
+                                    let buffer = entry.buffer();
+                                    if let Ok(value) = buffer.text().parse::<usize>() {
+                                        sender.input(AppMsg::ChangeValue(value));
+                                    }
+                                    buffer.delete_text(0, None);
+                                }
+                            }
+                        },
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            gtk::Label {
+                                set_label: "New game (side rows cols or samurai/twin/butterfly): ",
+                            },
+                            gtk::Entry {
+                                set_css_classes: &["white"],
+                                connect_activate[sender] => move |entry| {
+                                    let buffer = entry.buffer();
+                                    let shape = buffer.text();
+                                    buffer.delete_text(0, None);
+                                    sender.input(AppMsg::NewGame(shape.into()));
+                                }
+                            },
+                            gtk::CheckButton {
+                                set_label: Some("Non-consecutive"),
+                                #[watch]
+                                set_active: model.game.has_global_rule(GlobalRule::NonConsecutive),
+                                connect_toggled[sender] => move |button| {
+                                    sender.input(AppMsg::SetGlobalRule(GlobalRule::NonConsecutive, button.is_active()));
+                                },
+                            }
+                        },
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            gtk::Label {
+                                set_label: "Difficulty (easy/medium/hard/expert or score): ",
+                            },
+                            gtk::Entry {
+                                set_css_classes: &["white"],
+                                connect_activate[sender] => move |entry| {
+                                    let buffer = entry.buffer();
+                                    sender.input(AppMsg::SetDifficulty(buffer.text().into()));
+                                }
+                            }
+                        },
                         gtk::Box {
                             set_orientation: gtk::Orientation::Horizontal,
                             gtk::Label {
@@ -191,6 +680,29 @@ impl SimpleComponent for App {
                 },
 
                 gtk::Box {
+                    set_orientation: gtk::Orientation::Vertical,
+                    set_spacing: 5,
+
+                    gtk::Label {
+                        set_wrap: true,
+                        set_max_width_chars: 40,
+                        #[watch]
+                        set_visible: model.next_hint.is_some(),
+                        #[watch]
+                        set_label: &model
+                            .next_hint
+                            .as_ref()
+                            .map(|step| step.to_string())
+                            .unwrap_or_default(),
+                    },
+
+                    gtk::Button {
+                        set_label: "Apply hint",
+                        #[watch]
+                        set_visible: model.next_hint.is_some(),
+                        connect_clicked => AppMsg::ApplyHint,
+                    },
+
                     gtk::Label {
                         #[watch]
                         set_visible: model.show_rules,
@@ -204,10 +716,13 @@ impl SimpleComponent for App {
                 connect_key_pressed[sender] => move |_, keyval, _, _| {
                     if let Some(keyval) = keyval.to_unicode().and_then(|c| c.to_digit(36)) {
                         match keyval as usize {
-                            0..=N => sender.input(AppMsg::ChangeValue(keyval as usize)),
+                            // letters are commands, so larger digits go through the value box
+                            0..=9 => sender.input(AppMsg::ChangeValue(keyval as usize)),
                             15 => sender.input(AppMsg::Solve),
                             16 => sender.input(AppMsg::Generate),
                             17 => sender.input(AppMsg::Help),
+                            19 => sender.input(AppMsg::TogglePainting),
+                            23 => sender.input(AppMsg::NextHint),
                             25 => sender.input(AppMsg::Hints),
                             27 => sender.input(AppMsg::TogglePlanning),
                             31 => sender.input(AppMsg::ToggleRules),
@@ -239,53 +754,82 @@ impl SimpleComponent for App {
                     RuleOutput::RuleClicked(index) => AppMsg::RuleActive(index),
                 });
 
+        let game = game::Game::new(START_SIDE, START_BOX_ROWS, START_BOX_COLS).unwrap();
         let mut model = App {
             fields,
             rules,
             global_value: 0,
             rule_active: 0,
-            game: game::Game::new(N, R, C),
+            region_colors: region_colors(&game.get_regions(), &game.board()),
+            painting: None,
+            clues: vec![],
+            game,
             finished: 0,
             planning: true,
             show_rules: false,
             hints: false,
+            difficulty: None,
+            generating: None,
+            generations: 0,
+            next_hint: None,
+            eliminated: vec![],
         };
 
         let field_grid = model.fields.widget();
         let rule_grid = model.rules.widget();
         let widgets = view_output!();
 
-        for_pos!(N, |pos: Position| {
-            let color = pos.default_color(R, C);
-            model.fields.guard().push_back(color);
-        });
+        fill_fields(&mut model.fields.guard(), &model.game, &model.region_colors);
 
         ComponentParts { model, widgets }
     }
 
     fn update(&mut self, msg: Self::Input, sender: ComponentSender<Self>) {
-        let fields_guard = self.fields.guard();
+        let mut fields_guard = self.fields.guard();
         let mut rules_guard = self.rules.guard();
+        let side = self.game.get_side();
 
         match msg {
             AppMsg::FieldClicked(index) => {
-                if self.planning && rules_guard.len() > 0 {
-                    if rules_guard.is_empty() {
+                if let Some(regions) = &mut self.painting {
+                    if !(1..=side).contains(&self.global_value) {
                         return;
                     }
-                    let pos = Position::from_index(index).unwrap();
+                    let pos = Position::from_index(index, side).unwrap();
+                    regions[pos.row() - 1][pos.col() - 1] = self.global_value - 1;
+                    fields_guard.send(index, FieldMsg::SetValue(self.global_value));
+
+                    self.region_colors = region_colors(regions, &self.game.board());
+                    for (index, &color) in self.region_colors.iter().enumerate() {
+                        fields_guard.send(index, FieldMsg::ChangeColor(color));
+                    }
+                } else if self.planning && !rules_guard.is_empty() {
+                    let pos = Position::from_index(index, side).unwrap();
                     let rule: Rule = self.game.get_rule(self.rule_active);
 
-                    if rule.get_positions().contains(&pos) {
-                        self.game.remove_position_from_rule(self.rule_active, pos);
-                        fields_guard.send(index, FieldMsg::ChangeColor(pos.default_color(R, C)));
+                    let edited = if rule.get_positions().contains(&pos) {
+                        self.game.remove_position_from_rule(self.rule_active, pos)
                     } else {
-                        self.game.add_position_to_rule(self.rule_active, pos);
-                        fields_guard.send(index, FieldMsg::ChangeColor(self.rule_active));
+                        self.game.add_position_to_rule(self.rule_active, pos)
+                    };
+                    if let Err(e) = edited {
+                        popup(&format!("Invalid rule cell: {}", e));
+                        return;
                     }
+
+                    let rule: Rule = self.game.get_rule(self.rule_active);
+                    let color = match rule.get_positions().contains(&pos) {
+                        true => self.rule_active,
+                        false => self.region_colors[index],
+                    };
+                    fields_guard.send(index, FieldMsg::ChangeColor(color));
+                    refresh_markers(&mut fields_guard, &self.game);
                 } else {
-                    self.game
-                        .set_value(Position::from_index(index).unwrap(), self.global_value);
+                    let pos = Position::from_index(index, side).unwrap();
+                    if self.game.get_value(pos) != 0 {
+                        self.eliminated.clear();
+                    }
+                    self.game.set_value(pos, self.global_value);
                     let state = self.game.check_rules();
 
                     if let (None, None) = state {
@@ -297,21 +841,23 @@ impl SimpleComponent for App {
                 }
             }
             AppMsg::ChangeValue(value) => {
-                self.global_value = value;
+                if value <= self.game.get_digits() {
+                    self.global_value = value;
+                }
             }
 
             AppMsg::Hints => {
                 if self.hints {
-                    for_pos!(N, |pos: Position| {
-                        fields_guard.send(pos.index(), FieldMsg::SetValue(N + 1));
+                    for_pos!(side, |pos: Position| {
+                        fields_guard.send(pos.index(side), FieldMsg::SetValue(side + 1));
                     });
                     self.hints = false;
                 } else {
                     let solver = Solver::new(self.game.clone(), false);
 
-                    for_pos!(N, |pos: Position| {
+                    for_pos!(side, |pos: Position| {
                         let hints = solver.get_options(pos);
-                        fields_guard.send(pos.index(), FieldMsg::SetHints(hints));
+                        fields_guard.send(pos.index(side), FieldMsg::SetHints(hints));
                     });
                     self.hints = true;
                 }
@@ -324,12 +870,12 @@ impl SimpleComponent for App {
                 let solution = sol.get_solution();
 
                 if let Some(solution) = solution {
-                    for_pos!(N, |pos| {
+                    for_pos!(side, |pos| {
                         let sval = solution.get_value(pos);
                         let curval = self.game.get_value(pos);
                         if curval == 0 {
                             self.game.set_value(pos, sval);
-                            fields_guard.send(pos.index(), FieldMsg::SetValue(sval));
+                            fields_guard.send(pos.index(side), FieldMsg::SetValue(sval));
                         }
                     });
                     self.finished = 2;
@@ -355,29 +901,160 @@ impl SimpleComponent for App {
                     return;
                 }
 
-                if t == 0 {
-                    rules_guard.push_back((String::from("Permutation"), index));
-                    self.game.add_rule(Rule::Permutation(PermutationRule::new(
-                        vec![],
-                        self.game.get_base_rule_count() + index,
-                    )));
-                } else if t == 1 {
-                    let value = value.parse::<usize>();
-                    if let Ok(value) = value {
-                        rules_guard.push_back((format!("SUM: {}", value), index));
-                        self.game.add_rule(Rule::Sum(SumRule::new(
-                            vec![],
-                            value,
-                            self.game.get_base_rule_count() + index,
-                        )));
-                    } else {
-                        popup("Invalid rule value");
+                let rule_index = self.game.get_base_rule_count() + index;
+                let board = self.game.board();
+                match t {
+                    0 => {
+                        rules_guard.push_back((String::from("Permutation"), index));
+                        self.game
+                            .add_rule(Rule::Permutation(PermutationRule::new(vec![], rule_index)));
                     }
-                } else {
-                    rules_guard.push_back((String::from("Relation"), index));
-                    self.game.add_rule(Rule::Relation(RelationRule::new(
-                        self.game.get_base_rule_count() + index,
-                    )));
+                    1 => {
+                        let value = value.parse::<usize>();
+                        if let Ok(value) = value {
+                            rules_guard.push_back((format!("SUM: {}", value), index));
+                            self.game
+                                .add_rule(Rule::Sum(SumRule::new(vec![], value, rule_index)));
+                        } else {
+                            popup("Invalid rule value");
+                        }
+                    }
+                    2 => {
+                        rules_guard.push_back((String::from("Relation"), index));
+                        self.game
+                            .add_rule(Rule::Relation(RelationRule::new(rule_index)));
+                    }
+                    3 => {
+                        let sum = match value.trim() {
+                            "" => None,
+                            text => match text.parse::<usize>() {
+                                Ok(sum) => Some(sum),
+                                Err(_) => {
+                                    popup("Invalid rule value");
+                                    return;
+                                }
+                            },
+                        };
+                        let label = match sum {
+                            Some(sum) => format!("Cage: {}", sum),
+                            None => String::from("Cage"),
+                        };
+                        rules_guard.push_back((label, index));
+                        self.game
+                            .add_rule(Rule::Cage(CageRule::new(vec![], sum, rule_index)));
+                    }
+                    4 => {
+                        rules_guard.push_back((String::from("Thermometer"), index));
+                        self.game
+                            .add_rule(Rule::Thermometer(ThermometerRule::new(vec![], rule_index)));
+                    }
+                    5 => {
+                        let circle = match value.trim() {
+                            "" => 1,
+                            text => match text.parse::<usize>() {
+                                Ok(circle) if circle > 0 => circle,
+                                _ => {
+                                    popup("Invalid rule value");
+                                    return;
+                                }
+                            },
+                        };
+                        let arrow = Rule::Arrow(ArrowRule::new(vec![], circle, rule_index));
+                        if let Err(e) = arrow.check_shape(self.game.get_digits()) {
+                            popup(&format!("Invalid rule value: {}", e));
+                            return;
+                        }
+                        rules_guard.push_back((arrow_label(circle), index));
+                        self.game.add_rule(arrow);
+                    }
+                    6 => {
+                        let color = match value.as_str() {
+                            "black" => KropkiColor::Black,
+                            _ => KropkiColor::White,
+                        };
+                        rules_guard.push_back((format!("Kropki {}", color), index));
+                        self.game
+                            .add_rule(Rule::Kropki(KropkiRule::new(color, rule_index)));
+                    }
+                    7 => {
+                        let mark = match value.as_str() {
+                            "v" => XVMark::V,
+                            _ => XVMark::X,
+                        };
+                        rules_guard.push_back((format!("XV: {}", mark), index));
+                        self.game.add_rule(Rule::XV(XVRule::new(mark, rule_index)));
+                    }
+                    8 => match value.parse::<DigitFilter>() {
+                        Ok(filter) => {
+                            rules_guard.push_back((format!("Digits: {}", filter), index));
+                            self.game.add_rule(Rule::Restriction(RestrictionRule::new(
+                                vec![],
+                                filter,
+                                rule_index,
+                            )));
+                        }
+                        Err(e) => popup(&format!("Invalid rule value: {}", e)),
+                    },
+                    9 => match little_killer(&value, &board, rule_index) {
+                        Ok(rule) => {
+                            let label = format!(
+                                "Little killer: {}{}",
+                                rule.get_sum(),
+                                rule.get_direction()
+                            );
+                            rules_guard.push_back((label, index));
+                            self.game.add_rule(Rule::LittleKiller(rule));
+                            refresh_clues(fields_guard.widget(), &mut self.clues, &self.game);
+                        }
+                        Err(e) => popup(&format!("Invalid rule value: {}", e)),
+                    },
+                    10 => match sandwich(&value, &board, rule_index) {
+                        Ok(rule) => {
+                            rules_guard.push_back((format!("Sandwich: {}", rule.get_sum()), index));
+                            self.game.add_rule(Rule::Sandwich(rule));
+                            refresh_clues(fields_guard.widget(), &mut self.clues, &self.game);
+                        }
+                        Err(e) => popup(&format!("Invalid rule value: {}", e)),
+                    },
+                    11 => match value.parse::<LineKind>() {
+                        Ok(kind) => {
+                            rules_guard.push_back((format!("Line: {}", kind), index));
+                            self.game
+                                .add_rule(Rule::Line(LineRule::new(vec![], kind, rule_index)));
+                        }
+                        Err(e) => popup(&format!("Invalid rule value: {}", e)),
+                    },
+                    12 => match quad(&value, &board, rule_index) {
+                        Ok(rule) => {
+                            rules_guard
+                                .push_back((format!("Quad: {:?}", rule.get_digits()), index));
+                            self.game.add_rule(Rule::Quad(rule));
+                            refresh_clues(fields_guard.widget(), &mut self.clues, &self.game);
+                        }
+                        Err(e) => popup(&format!("Invalid rule value: {}", e)),
+                    },
+                    _ => match ExpressionRule::new(&value, rule_index) {
+                        Ok(rule)
+                            if rule
+                                .positions
+                                .iter()
+                                .any(|pos| pos.row().max(pos.col()) > side) =>
+                        {
+                            popup(&format!(
+                                "Invalid rule value: cells go up to r{}c{}",
+                                side, side
+                            ));
+                        }
+                        Ok(rule) => match avoid_blocked(&rule.positions, &board) {
+                            Ok(()) => {
+                                rules_guard
+                                    .push_back((format!("Expr: {}", rule.get_source()), index));
+                                self.game.add_rule(Rule::Expression(rule));
+                            }
+                            Err(e) => popup(&format!("Invalid rule value: {}", e)),
+                        },
+                        Err(e) => popup(&format!("Invalid rule value: {}", e)),
+                    },
                 }
             }
 
@@ -385,16 +1062,15 @@ impl SimpleComponent for App {
                 let rule = self.game.get_rule(self.rule_active);
 
                 for pos in rule.get_positions() {
-                    let id = pos.index();
-                    let color = pos.default_color(R, C);
-                    fields_guard.send(id, FieldMsg::ChangeColor(color));
+                    let id = pos.index(side);
+                    fields_guard.send(id, FieldMsg::ChangeColor(self.region_colors[id]));
                 }
 
                 self.rule_active = index;
 
                 let rule = self.game.get_rule(self.rule_active);
                 for pos in rule.get_positions() {
-                    let index = pos.index();
+                    let index = pos.index(side);
                     fields_guard.send(index, FieldMsg::ChangeColor(self.rule_active));
                 }
             }
@@ -402,14 +1078,28 @@ impl SimpleComponent for App {
             AppMsg::Help => {
                 popup(
                     "Help:\n\
-                    press 0-N to choose current setting value,\n\
+                    press 0-9 (or type into the value box) to choose current setting value,\n\
                     you can move with arrows to select field and press Enter to set value,\n\
                     clicking on field will also set current setting value,\n\
                     three types of rules are available: for each one select corresponding box from add rule, then select positions to apply,\n\
+                    thermometer cells are selected in order, starting from the bulb,\n\
+                    for an arrow select the circle cells first, then the shaft,\n\
+                    a digits rule limits its cells to even, odd, low or high digits, or to a list like '1 3 5',\n\
+                    a kropki dot joins two adjacent cells: white for consecutive digits, black when one is double the other,\n\
+                    an XV marker joins two adjacent cells summing to 10 (X) or 5 (V),\n\
+                    a little killer clue sits on the frame around the board (row or column 0 or side + 1) and gives the sum of the diagonal it points along, e.g. '15 0 2 dr',\n\
+                    a sandwich clue stands beside a row or column and gives the sum of the digits between its 1 and its largest digit, e.g. '12 0 4',\n\
+                    a line is drawn by clicking neighbouring cells in order: a palindrome reads the same both ways, a renban holds consecutive digits in any order, whispers neighbours differ by at least 5 (on 9x9) and a region sum line has the same sum in every box it passes,\n\
+                    a quad is typed as the top-left of its four cells followed by up to four digits that must appear among them, e.g. '2 3 1 5 9',\n\
+                    an expression compares sums, differences and products of cells like 'r1c1 + r1c2 == 2 * r3c3' or 'abs(r5c5 - r6c6) > 2' (==, !=, <, <=, >, >=), or lists cells with different digits as 'distinct(r1c1, r2c2, r3c3)',\n\
+                    anti-knight and anti-king forbid equal digits a chess knight's or king's move apart,\n\
                     'f' to finish game using solver,\n\
                     'r' to toggle planning mode,\n\
                     'p' to show all possible values for every field,\n\
+                    'n' to show the next logical step, which can then be applied with the 'Apply hint' button,\n\
                     'g' to generate sudoku game,\n\
+                    'j' to paint jigsaw regions: every cell shows its region number, choose a value and click cells to move them into that region, press 'j' again to start a game with the painted regions,\n\
+                    type 'side rows cols' (e.g. '6 2 3') or 'samurai', 'twin', 'butterfly' into the new game box to start a board of another size or overlapping grids, tick 'Non-consecutive' to forbid consecutive digits in neighbouring cells (the choice carries over to new games),\n\
                 ",
                 );
             }
@@ -420,20 +1110,65 @@ impl SimpleComponent for App {
             }
 
             AppMsg::Generate => {
+                if self.generating.is_some() {
+                    popup("A puzzle is already being generated");
+                    return;
+                }
+                self.generations += 1;
+                self.generating = Some(self.generations);
+
+                // generating a large board takes a while, so it runs off the UI thread
+                let (id, game, difficulty) = (self.generations, self.game.clone(), self.difficulty);
+                let input = sender.input_sender().clone();
+                std::thread::spawn(move || {
+                    let generated = match difficulty {
+                        Some(difficulty) => generate_with_difficulty(game, difficulty, 20),
+                        None => {
+                            generate(game).and_then(|game| rate(&game).map(|rating| (game, rating)))
+                        }
+                    };
+                    input.emit(AppMsg::Generated(id, generated));
+                });
+            }
+
+            AppMsg::Generated(id, generated) => {
+                if self.generating != Some(id) {
+                    return;
+                }
+                self.generating = None;
                 self.finished = 0;
-                let game = generate(self.game.clone());
-                if let Some(game) = game {
+
+                if let Some((game, rating)) = generated {
+                    if let Some(step) = self.next_hint.take() {
+                        restore_colors(&mut fields_guard, &step.cells, side, &self.region_colors);
+                    }
+                    self.eliminated.clear();
                     self.game = game;
-                    for_pos!(N, |pos: Position| {
+                    for_pos!(side, |pos: Position| {
                         let sval = self.game.get_value(pos);
                         self.game.set_value(pos, sval);
-                        fields_guard.send(pos.index(), FieldMsg::SetValue(sval));
+                        fields_guard.send(pos.index(side), FieldMsg::SetValue(sval));
                     });
+                    popup(&format!("Generated puzzle with rating {}", rating));
+                } else if self.difficulty.is_some() {
+                    popup("Could not generate a puzzle in the requested difficulty, try again");
                 } else {
                     sender.input(AppMsg::Wrong);
                 }
             }
 
+            AppMsg::SetDifficulty(text) => {
+                if text.trim().is_empty() {
+                    self.difficulty = None;
+                    return;
+                }
+
+                match text.parse::<Difficulty>() {
+                    Ok(difficulty) => self.difficulty = Some(difficulty),
+                    Err(e) => popup(&format!("Invalid difficulty: {}", e)),
+                }
+            }
+
             AppMsg::ToggleRules => {
                 self.show_rules = !self.show_rules;
             }
@@ -457,6 +1192,7 @@ impl SimpleComponent for App {
                 match loaded {
                     Ok(game) => {
                         self.game = game;
+                        self.generating = None;
                     }
                     Err(e) => {
                         popup(&format!("Error loading game: {}", e));
@@ -464,12 +1200,24 @@ impl SimpleComponent for App {
                     }
                 }
                 rules_guard.clear();
+                self.rule_active = 0;
+                self.hints = false;
+                self.next_hint = None;
+                self.eliminated.clear();
+
+                self.painting = None;
 
-                for_pos!(N, |pos: Position| {
+                let side = self.game.get_side();
+                self.region_colors = region_colors(&self.game.get_regions(), &self.game.board());
+                fill_fields(&mut fields_guard, &self.game, &self.region_colors);
+
+                for_pos!(side, |pos: Position| {
                     let sval = self.game.get_value(pos);
                     self.game.set_value(pos, sval);
-                    fields_guard.send(pos.index(), FieldMsg::SetValue(sval));
+                    fields_guard.send(pos.index(side), FieldMsg::SetValue(sval));
                 });
+                refresh_markers(&mut fields_guard, &self.game);
+                refresh_clues(fields_guard.widget(), &mut self.clues, &self.game);
 
                 for rule in self
                     .game
@@ -483,11 +1231,148 @@ impl SimpleComponent for App {
                             Rule::Permutation(_) => "Permutation".to_string(),
                             Rule::Sum(sum) => format!("Sum: {}", sum.get_sum()),
                             Rule::Relation(_) => "Relation".to_string(),
+                            Rule::Cage(cage) => match cage.get_sum() {
+                                Some(sum) => format!("Cage: {}", sum),
+                                None => "Cage".to_string(),
+                            },
+                            Rule::Thermometer(_) => "Thermometer".to_string(),
+                            Rule::Arrow(arrow) => arrow_label(arrow.get_circle()),
+                            Rule::Kropki(dot) => format!("Kropki {}", dot.get_color()),
+                            Rule::XV(xv) => format!("XV: {}", xv.get_mark()),
+                            Rule::Restriction(r) => format!("Digits: {}", r.get_filter()),
+                            Rule::LittleKiller(r) => {
+                                format!("Little killer: {}{}", r.get_sum(), r.get_direction())
+                            }
+                            Rule::Sandwich(r) => format!("Sandwich: {}", r.get_sum()),
+                            Rule::Line(r) => format!("Line: {}", r.get_kind()),
+                            Rule::Quad(r) => format!("Quad: {:?}", r.get_digits()),
+                            Rule::Expression(r) => format!("Expr: {}", r.get_source()),
+                            Rule::Custom(r) => format!("Custom: {}", r.get().tag()),
                         },
                         index,
                     ));
                 }
             }
+
+            AppMsg::NewGame(shape) => {
+                let dims: Vec<usize> = shape
+                    .split_whitespace()
+                    .filter_map(|x| x.parse().ok())
+                    .collect();
+
+                let non_consecutive = self.game.has_global_rule(GlobalRule::NonConsecutive);
+                let game = match (shape.parse::<game::Layout>(), &dims[..]) {
+                    (Ok(layout), _) => Ok(layout.game()),
+                    (_, &[side, sub_rows, sub_cols]) => game::Game::new(side, sub_rows, sub_cols),
+                    _ => Err(String::from(
+                        "expected: side rows cols (rows * cols = side) or samurai, twin, butterfly",
+                    )),
+                };
+                self.game = match game {
+                    Ok(game) => game,
+                    Err(e) => {
+                        popup(&format!("Invalid game shape: {}", e));
+                        return;
+                    }
+                };
+                self.game
+                    .set_global_rule(GlobalRule::NonConsecutive, non_consecutive);
+                self.region_colors = region_colors(&self.game.get_regions(), &self.game.board());
+                self.painting = None;
+                self.generating = None;
+                self.global_value = 0;
+                self.rule_active = 0;
+                self.finished = 0;
+                self.hints = false;
+                self.next_hint = None;
+                self.eliminated.clear();
+                rules_guard.clear();
+                fill_fields(&mut fields_guard, &self.game, &self.region_colors);
+                refresh_clues(fields_guard.widget(), &mut self.clues, &self.game);
+            }
+
+            AppMsg::TogglePainting => {
+                if !self.game.get_grids().is_empty() {
+                    popup("Regions can only be painted on a single grid");
+                    return;
+                }
+                let Some(regions) = self.painting.take() else {
+                    let regions = self.game.get_regions();
+                    for_pos!(side, |pos: Position| {
+                        let region = regions[pos.row() - 1][pos.col() - 1];
+                        fields_guard.send(pos.index(side), FieldMsg::SetValue(region + 1));
+                    });
+                    self.painting = Some(regions);
+                    return;
+                };
+
+                match game::Game::with_regions(side, &regions) {
+                    Ok(mut game) => {
+                        game.set_global_rule(
+                            GlobalRule::NonConsecutive,
+                            self.game.has_global_rule(GlobalRule::NonConsecutive),
+                        );
+                        self.game = game;
+                        self.generating = None;
+                        self.region_colors = region_colors(&regions, &self.game.board());
+                        self.rule_active = 0;
+                        self.finished = 0;
+                        self.hints = false;
+                        self.next_hint = None;
+                        self.eliminated.clear();
+                        rules_guard.clear();
+                        fill_fields(&mut fields_guard, &self.game, &self.region_colors);
+                        refresh_clues(fields_guard.widget(), &mut self.clues, &self.game);
+                    }
+                    Err(e) => {
+                        popup(&format!("Invalid regions: {}", e));
+                        self.painting = Some(regions);
+                    }
+                }
+            }
+
+            AppMsg::SetGlobalRule(rule, enabled) => {
+                self.game.set_global_rule(rule, enabled);
+            }
+
+            AppMsg::NextHint => {
+                if let Some(step) = self.next_hint.take() {
+                    restore_colors(&mut fields_guard, &step.cells, side, &self.region_colors);
+                }
+
+                let mut solver = LogicalSolver::new(&self.game);
+                for &(pos, digit) in &self.eliminated {
+                    solver.eliminate(pos, digit);
+                }
+
+                match solver.next_step() {
+                    Some(step) => {
+                        for pos in &step.cells {
+                            fields_guard.send(pos.index(side), FieldMsg::ChangeColor(HINT_COLOR));
+                        }
+                        self.next_hint = Some(step);
+                    }
+                    None => popup("No logical step found for the current board"),
+                }
+            }
+
+            AppMsg::ApplyHint => {
+                let Some(step) = self.next_hint.take() else {
+                    return;
+                };
+                restore_colors(&mut fields_guard, &step.cells, side, &self.region_colors);
+
+                for &(pos, digit) in &step.placements {
+                    self.game.set_value(pos, digit);
+                    fields_guard.send(pos.index(side), FieldMsg::SetValue(digit));
+                }
+                self.eliminated.extend(step.eliminations.iter().copied());
+
+                if let (None, None) = self.game.check_rules() {
+                    self.finished = 0;
+                    sender.input(AppMsg::Finished);
+                }
+            }
         }
     }
 }
@@ -5,9 +5,9 @@ use relm4::factory::{
 };
 use relm4::RelmWidgetExt;
 
-const N: usize = 9;
-const COLOR_LIST: [&str; 9] = [
-    "red", "green", "purple", "orange", "pink", "brown", "yellow", "white", "grey",
+const COLOR_LIST: [&str; 12] = [
+    "red", "green", "purple", "orange", "pink", "brown", "yellow", "white", "grey", "blue", "sand",
+    "mint",
 ];
 
 #[derive(Debug)]
@@ -15,7 +15,10 @@ pub struct Field {
     pub value: usize,
     pub display_value: String,
     pub color: usize,
+    pub marker: Option<&'static str>,
     pub index: usize,
+    pub side: usize,
+    pub blocked: bool,
 }
 
 #[derive(Debug)]
@@ -24,6 +27,7 @@ pub enum FieldMsg {
     ChangeValue,
     SetHints(Vec<usize>),
     SetValue(usize),
+    SetMarker(Option<&'static str>),
 }
 
 #[derive(Debug)]
@@ -38,8 +42,9 @@ pub struct FieldWidgets {
 impl Position<GridPosition, DynamicIndex> for Field {
     fn position(&self, index: &DynamicIndex) -> GridPosition {
         let index = index.current_index();
-        let x = index % N;
-        let y = index / N;
+        // row and column 0 are left free for the clues around the board
+        let x = index % self.side + 1;
+        let y = index / self.side + 1;
         GridPosition {
             column: y as i32,
             row: x as i32,
@@ -50,7 +55,7 @@ impl Position<GridPosition, DynamicIndex> for Field {
 }
 
 impl FactoryComponent for Field {
-    type Init = usize;
+    type Init = (usize, usize, bool);
     type Input = FieldMsg;
     type Output = FieldOutput;
     type CommandOutput = ();
@@ -69,14 +74,21 @@ impl FactoryComponent for Field {
         root
     }
 
-    fn init_model(color: Self::Init, index: &DynamicIndex, _sender: FactorySender<Self>) -> Self {
+    fn init_model(
+        (color, side, blocked): Self::Init,
+        index: &DynamicIndex,
+        _sender: FactorySender<Self>,
+    ) -> Self {
         let index = index.current_index();
 
         Self {
             value: 0,
             display_value: String::from("_"),
             color,
+            marker: None,
             index,
+            side,
+            blocked,
         }
     }
 
@@ -95,6 +107,8 @@ impl FactoryComponent for Field {
                     set_css_classes: choose_color!(self.color),
                     set_label: &self.display_value,
                     set_size_request: (100, 50),
+                    // cells outside every grid keep their place but are not shown
+                    set_visible: !self.blocked,
                     connect_clicked => FieldMsg::ChangeValue,
                     set_margin_all: 5,
                 }
@@ -112,7 +126,7 @@ impl FactoryComponent for Field {
                     .unwrap();
             }
             FieldMsg::SetValue(value) => {
-                if value != N + 1 {
+                if value != self.side + 1 {
                     self.value = value;
                 }
                 self.display_value = match self.value {
@@ -123,6 +137,9 @@ impl FactoryComponent for Field {
             FieldMsg::ChangeColor(color) => {
                 self.color = color;
             }
+            FieldMsg::SetMarker(marker) => {
+                self.marker = marker;
+            }
             FieldMsg::SetHints(hint) => {
                 if self.value == 0 {
                     self.display_value = hint
@@ -137,6 +154,9 @@ impl FactoryComponent for Field {
 
     fn update_view(&self, widgets: &mut Self::Widgets, _sender: FactorySender<Self>) {
         widgets.label.set_label(&self.display_value);
-        widgets.label.set_css_classes(choose_color!(self.color));
+
+        let mut classes = vec![COLOR_LIST[self.color]];
+        classes.extend(self.marker);
+        widgets.label.set_css_classes(&classes);
     }
 }
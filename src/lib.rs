@@ -1,2 +1,2 @@
 pub mod logic;
-pub mod macros;
\ No newline at end of file
+pub mod macros;
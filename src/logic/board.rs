@@ -1,8 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::fmt::{self, Display};
 
-const N: usize = 9;
-
 #[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Position {
     row: usize,
@@ -10,32 +8,50 @@ pub struct Position {
 }
 
 impl Position {
-    pub fn new(row: usize, col: usize) -> Option<Self> {
-        if (1..=N).contains(&row) && (1..=N).contains(&col) {
+    pub fn new(row: usize, col: usize, side: usize) -> Option<Self> {
+        if (1..=side).contains(&row) && (1..=side).contains(&col) {
             Some(Position { row, col })
         } else {
             None
         }
     }
 
-    pub fn from_index(index: usize) -> Option<Self> {
-        let row = index % N + 1;
-        let col = index / N + 1;
-        Position::new(row, col)
+    pub fn from_index(index: usize, side: usize) -> Option<Self> {
+        if side == 0 {
+            return None;
+        }
+        let row = index % side + 1;
+        let col = index / side + 1;
+        Position::new(row, col, side)
     }
 
     pub fn coords(&self) -> (usize, usize) {
         (self.row - 1, self.col - 1)
     }
 
-    pub fn index(&self) -> usize {
-        (self.row - 1) + (self.col - 1) * N
+    pub fn index(&self, side: usize) -> usize {
+        (self.row - 1) + (self.col - 1) * side
     }
 
     pub fn default_color(&self, r: usize, c: usize) -> usize {
         7 + (((self.row - 1) / r + (self.col - 1) / c) % 2)
     }
 
+    pub fn offset(&self, rows: isize, cols: isize, side: usize) -> Option<Self> {
+        let row = self.row.checked_add_signed(rows)?;
+        let col = self.col.checked_add_signed(cols)?;
+        Position::new(row, col, side)
+    }
+
+    pub fn is_adjacent(&self, other: &Position) -> bool {
+        self.row.abs_diff(other.row) + self.col.abs_diff(other.col) == 1
+    }
+
+    /// Whether the cells are neighbours, diagonals included.
+    pub fn touches(&self, other: &Position) -> bool {
+        self != other && self.row.abs_diff(other.row) <= 1 && self.col.abs_diff(other.col) <= 1
+    }
+
     pub fn row(&self) -> usize {
         self.row
     }
@@ -51,11 +67,123 @@ impl std::fmt::Debug for Position {
     }
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+/// A clue spot on the frame around the board, in row or column 0 or `side + 1`.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Outside {
+    row: usize,
+    col: usize,
+}
+
+impl Outside {
+    pub fn new(row: usize, col: usize, side: usize) -> Option<Self> {
+        let frame = side + 1;
+        let on_frame = row == 0 || row == frame || col == 0 || col == frame;
+        if row <= frame && col <= frame && on_frame {
+            Some(Outside { row, col })
+        } else {
+            None
+        }
+    }
+
+    /// Step pointing into the board from a clue beside a row or column; corners have none.
+    pub fn inward(&self, side: usize) -> Option<(isize, isize)> {
+        let frame = side + 1;
+        let inside = |line: usize| (1..=side).contains(&line);
+        match (self.row, self.col) {
+            (0, col) if inside(col) => Some((1, 0)),
+            (row, col) if row == frame && inside(col) => Some((-1, 0)),
+            (row, 0) if inside(row) => Some((0, 1)),
+            (row, col) if col == frame && inside(row) => Some((0, -1)),
+            _ => None,
+        }
+    }
+
+    /// Cells met when stepping from the clue by `(rows, cols)` until leaving the board.
+    pub fn ray(&self, rows: isize, cols: isize, side: usize) -> Vec<Position> {
+        let mut cells = vec![];
+        let (mut row, mut col) = (self.row, self.col);
+        while let (Some(next_row), Some(next_col)) =
+            (row.checked_add_signed(rows), col.checked_add_signed(cols))
+        {
+            let Some(pos) = Position::new(next_row, next_col, side) else {
+                break;
+            };
+            cells.push(pos);
+            (row, col) = (next_row, next_col);
+        }
+        cells
+    }
+
+    pub fn row(&self) -> usize {
+        self.row
+    }
+
+    pub fn col(&self) -> usize {
+        self.col
+    }
+}
+
+impl std::fmt::Debug for Outside {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {})", self.row, self.col)
+    }
+}
+
+/// The point where four cells meet, named after the top-left one of them.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Corner {
+    row: usize,
+    col: usize,
+}
+
+impl Corner {
+    pub fn new(row: usize, col: usize, side: usize) -> Option<Self> {
+        if (1..side).contains(&row) && (1..side).contains(&col) {
+            Some(Corner { row, col })
+        } else {
+            None
+        }
+    }
+
+    pub fn cells(&self) -> [Position; 4] {
+        let (row, col) = (self.row, self.col);
+        [
+            Position { row, col },
+            Position { row, col: col + 1 },
+            Position { row: row + 1, col },
+            Position {
+                row: row + 1,
+                col: col + 1,
+            },
+        ]
+    }
+
+    pub fn row(&self) -> usize {
+        self.row
+    }
+
+    pub fn col(&self) -> usize {
+        self.col
+    }
+}
+
+impl std::fmt::Debug for Corner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {})", self.row, self.col)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Board {
     side: usize,
     filled: usize,
     board: Vec<Vec<usize>>,
+    /// Largest digit, when the board is a canvas holding several smaller grids.
+    #[serde(default)]
+    digits: Option<usize>,
+    /// Cells of the canvas that belong to no grid; empty when every cell is in use.
+    #[serde(default)]
+    blocked: Vec<Vec<bool>>,
 }
 
 impl Board {
@@ -64,6 +192,32 @@ impl Board {
             side,
             filled: 0,
             board: vec![vec![0; side]; side],
+            digits: None,
+            blocked: vec![],
+        }
+    }
+
+    /// A `side`x`side` canvas for grids of `digits` digits, where `blocked` cells do not exist.
+    pub fn with_blocked(side: usize, digits: usize, blocked: &[Position]) -> Self {
+        let mut board = Board::new(side);
+        board.digits = Some(digits);
+        if !blocked.is_empty() {
+            board.blocked = vec![vec![false; side]; side];
+            for pos in blocked {
+                board.blocked[pos.row - 1][pos.col - 1] = true;
+            }
+        }
+        board
+    }
+
+    /// The same canvas with every digit removed.
+    pub fn cleared(&self) -> Self {
+        Board {
+            side: self.side,
+            filled: 0,
+            board: vec![vec![0; self.side]; self.side],
+            digits: self.digits,
+            blocked: self.blocked.clone(),
         }
     }
 
@@ -78,13 +232,60 @@ impl Board {
     pub fn get_side(&self) -> usize {
         self.side
     }
+
+    /// Largest digit a cell can hold.
+    pub fn get_digits(&self) -> usize {
+        self.digits.unwrap_or(self.side)
+    }
+
+    pub fn is_blocked(&self, pos: Position) -> bool {
+        !self.blocked.is_empty() && self.blocked[pos.row - 1][pos.col - 1]
+    }
+
+    /// Whether the cell lies on the canvas and is not blocked.
+    pub fn contains(&self, pos: Position) -> bool {
+        (1..=self.side).contains(&pos.row)
+            && (1..=self.side).contains(&pos.col)
+            && !self.is_blocked(pos)
+    }
+
+    /// Every cell that is not blocked, column by column like `for_pos!`.
+    pub fn cells(&self) -> Vec<Position> {
+        (1..=self.side)
+            .flat_map(|col| (1..=self.side).map(move |row| Position { row, col }))
+            .filter(|&pos| !self.is_blocked(pos))
+            .collect()
+    }
+
+    pub fn is_consistent(&self) -> bool {
+        let digits = self.get_digits();
+        let blocked_fits = self.blocked.is_empty()
+            || (self.blocked.len() == self.side
+                && self.blocked.iter().all(|row| row.len() == self.side));
+
+        (1..=self.side).contains(&digits)
+            && blocked_fits
+            && self.board.len() == self.side
+            && self
+                .board
+                .iter()
+                .all(|row| row.len() == self.side && row.iter().all(|&v| v <= digits))
+            && (1..=self.side)
+                .flat_map(|row| (1..=self.side).map(move |col| Position { row, col }))
+                .all(|pos| !self.is_blocked(pos) || self.get_value(pos) == 0)
+    }
 }
 
 impl Display for Board {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for row in &self.board {
-            for &cell in row {
-                let cell_str = if cell == 0 { "_" } else { &cell.to_string() };
+        for (r, row) in self.board.iter().enumerate() {
+            for (c, &cell) in row.iter().enumerate() {
+                let blocked = !self.blocked.is_empty() && self.blocked[r][c];
+                let cell_str = match cell {
+                    _ if blocked => String::from(" "),
+                    0 => String::from("_"),
+                    _ => cell.to_string(),
+                };
                 write!(f, "{} ", cell_str)?;
             }
             writeln!(f)?;
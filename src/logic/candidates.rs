@@ -0,0 +1,181 @@
+use super::board::{Board, Position};
+use super::digits::DigitSet;
+
+pub struct Candidates {
+    side: usize,
+    digits: usize,
+    board: Board,
+    options: Vec<DigitSet>,
+    trail: Vec<(usize, DigitSet, usize)>,
+}
+
+impl Candidates {
+    /// Empty candidates on the canvas of `layout`; blocked cells get no options at all.
+    pub fn new(layout: &Board) -> Self {
+        let (side, digits) = (layout.get_side(), layout.get_digits());
+        let mut options = vec![DigitSet::empty(); side * side];
+        for pos in layout.cells() {
+            options[pos.index(side)] = DigitSet::full(digits);
+        }
+
+        Candidates {
+            side,
+            digits,
+            board: layout.cleared(),
+            options,
+            trail: vec![],
+        }
+    }
+
+    pub fn side(&self) -> usize {
+        self.side
+    }
+
+    pub fn digits(&self) -> usize {
+        self.digits
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn value(&self, pos: Position) -> usize {
+        self.board.get_value(pos)
+    }
+
+    pub fn options(&self, pos: Position) -> DigitSet {
+        self.options[pos.index(self.side)]
+    }
+
+    pub fn domain(&self, pos: Position) -> DigitSet {
+        match self.value(pos) {
+            0 => self.options(pos),
+            v => DigitSet::single(v),
+        }
+    }
+
+    pub fn bounds(&self, pos: Position) -> (usize, usize) {
+        let domain = self.domain(pos);
+        (domain.min().unwrap_or(0), domain.max().unwrap_or(0))
+    }
+
+    pub fn assign(&mut self, pos: Position, digit: usize) {
+        self.save(pos);
+        self.board.set_value(pos, digit);
+        self.options[pos.index(self.side)] = DigitSet::single(digit);
+    }
+
+    /// Keeps only `keep` in the domain of `pos`. Returns `None` when nothing is left,
+    /// otherwise whether the domain shrank.
+    pub fn restrict(&mut self, pos: Position, keep: DigitSet) -> Option<bool> {
+        let value = self.value(pos);
+        if value > 0 {
+            return keep.contains(value).then_some(false);
+        }
+
+        let current = self.options(pos);
+        let next = current.intersection(keep);
+        if next.is_empty() {
+            return None;
+        }
+        if next == current {
+            return Some(false);
+        }
+
+        self.save(pos);
+        self.options[pos.index(self.side)] = next;
+        Some(true)
+    }
+
+    pub fn remove(&mut self, pos: Position, digit: usize) -> Option<bool> {
+        self.restrict(
+            pos,
+            DigitSet::full(self.digits).difference(DigitSet::single(digit)),
+        )
+    }
+
+    pub fn mark(&self) -> usize {
+        self.trail.len()
+    }
+
+    /// Flags, by cell index, every cell changed since `mark`.
+    pub fn changed_since(&self, mark: usize) -> Vec<bool> {
+        let mut changed = vec![false; self.side * self.side];
+        for &(cell, ..) in &self.trail[mark..] {
+            changed[cell] = true;
+        }
+        changed
+    }
+
+    pub fn undo_to(&mut self, mark: usize) {
+        while self.trail.len() > mark {
+            let (cell, options, value) = self.trail.pop().unwrap();
+            self.options[cell] = options;
+            self.board
+                .set_value(Position::from_index(cell, self.side).unwrap(), value);
+        }
+    }
+
+    fn save(&mut self, pos: Position) {
+        let cell = pos.index(self.side);
+        self.trail
+            .push((cell, self.options[cell], self.board.get_value(pos)));
+    }
+}
+
+/// The part of `Candidates` a rule from another crate may touch: it can read the digits a cell
+/// can still take and remove some of them, while the solver keeps control of the trail.
+pub struct Domains<'a> {
+    state: &'a mut Candidates,
+}
+
+impl<'a> Domains<'a> {
+    pub(crate) fn new(state: &'a mut Candidates) -> Self {
+        Domains { state }
+    }
+
+    pub fn digits(&self) -> usize {
+        self.state.digits()
+    }
+
+    /// Digits `pos` can still take, a single one when it is already filled.
+    pub fn get(&self, pos: Position) -> DigitSet {
+        self.state.domain(pos)
+    }
+
+    /// Same as `Candidates::remove`.
+    pub fn remove(&mut self, pos: Position, digit: usize) -> Option<bool> {
+        self.state.remove(pos, digit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_restores_everything_since_the_mark() {
+        let mut state = Candidates::new(&Board::new(4));
+        let (a, b) = (
+            Position::new(1, 1, 4).unwrap(),
+            Position::new(2, 3, 4).unwrap(),
+        );
+
+        state.remove(a, 1).unwrap();
+        let mark = state.mark();
+        state.assign(b, 3);
+        assert_eq!(state.remove(a, 2), Some(true));
+        assert_eq!(state.remove(a, 2), Some(false));
+        assert_eq!(state.restrict(a, DigitSet::single(2)), None);
+
+        let changed = state.changed_since(mark);
+        assert!(changed[a.index(4)] && changed[b.index(4)]);
+        assert_eq!(changed.iter().filter(|&&c| c).count(), 2);
+
+        state.undo_to(mark);
+        assert_eq!(state.options(a).to_vec(), [2, 3, 4]);
+        assert_eq!(state.value(b), 0);
+        assert_eq!(state.options(b).to_vec(), [1, 2, 3, 4]);
+        assert!(!state.changed_since(mark).contains(&true));
+    }
+}
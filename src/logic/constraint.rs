@@ -0,0 +1,323 @@
+use super::board::{Board, Position};
+use super::candidates::Domains;
+use super::rules::{
+    ArrowRule, CageRule, ExpressionRule, KropkiRule, LineRule, LittleKillerRule, PermutationRule,
+    QuadRule, RelationRule, RestrictionRule, RuleCheckResult, SandwichRule, SumRule,
+    ThermometerRule, XVRule,
+};
+use serde::de::{DeserializeOwned, Error as _};
+use serde::ser::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::any::TypeId;
+use std::fmt;
+use std::sync::RwLock;
+
+/// What the game and the solver need to know about a rule. The built-in rules implement it too,
+/// but are saved as their own `Rule` variants; other crates add rules through `CustomRule`
+/// after calling `register`.
+pub trait Constraint: fmt::Debug + Send + Sync + ConstraintData {
+    /// Name saved next to the rule's data, used to find the rule in the registry when loading.
+    fn tag(&self) -> &'static str;
+
+    fn positions(&self) -> Vec<Position>;
+
+    fn check(&self, board: &Board) -> RuleCheckResult;
+
+    /// Removes candidates the rule rules out. `None` means a contradiction, otherwise whether
+    /// anything changed. The solver already rejects boards that `check` reports as broken,
+    /// and handles the built-in rules itself, so they keep the default.
+    fn propagate(&self, _domains: &mut Domains) -> Option<bool> {
+        Some(false)
+    }
+}
+
+/// Cloning and saving a boxed rule; implemented for every `Clone + Serialize` constraint.
+pub trait ConstraintData {
+    fn clone_box(&self) -> Box<dyn Constraint>;
+    fn to_value(&self) -> Result<serde_json::Value, serde_json::Error>;
+}
+
+impl<T: Constraint + Clone + Serialize + 'static> ConstraintData for T {
+    fn clone_box(&self) -> Box<dyn Constraint> {
+        Box::new(self.clone())
+    }
+
+    fn to_value(&self) -> Result<serde_json::Value, serde_json::Error> {
+        serde_json::to_value(self)
+    }
+}
+
+type Loader = fn(serde_json::Value) -> Result<Box<dyn Constraint>, String>;
+
+static REGISTRY: RwLock<Vec<(&'static str, TypeId, Loader)>> = RwLock::new(Vec::new());
+
+fn load_as<T: Constraint + DeserializeOwned + 'static>(
+    data: serde_json::Value,
+) -> Result<Box<dyn Constraint>, String> {
+    match serde_json::from_value::<T>(data) {
+        Ok(rule) => Ok(Box::new(rule)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+macro_rules! built_in {
+    ($($rule:ty => $tag:literal),* $(,)?) => {
+        $(
+            impl Constraint for $rule {
+                fn tag(&self) -> &'static str {
+                    $tag
+                }
+
+                fn positions(&self) -> Vec<Position> {
+                    self.positions.clone()
+                }
+
+                fn check(&self, board: &Board) -> RuleCheckResult {
+                    <$rule>::check(self, board)
+                }
+            }
+        )*
+
+        /// Tags of the built-in rules, which custom rules cannot take.
+        const BUILT_IN: &[&str] = &[$($tag),*];
+    };
+}
+
+built_in! {
+    SumRule => "sum",
+    PermutationRule => "permutation",
+    RelationRule => "relation",
+    CageRule => "cage",
+    ThermometerRule => "thermometer",
+    ArrowRule => "arrow",
+    KropkiRule => "kropki",
+    XVRule => "xv",
+    RestrictionRule => "restriction",
+    LittleKillerRule => "little killer",
+    SandwichRule => "sandwich",
+    LineRule => "line",
+    QuadRule => "quad",
+    ExpressionRule => "expression",
+}
+
+/// Makes rules of type `T` loadable under `tag`, which must be what their `Constraint::tag` returns;
+/// `CustomRule::new` refuses rules whose tag is not registered for their type.
+pub fn register<T: Constraint + DeserializeOwned + 'static>(
+    tag: &'static str,
+) -> Result<(), String> {
+    let mut registry = REGISTRY.write().unwrap_or_else(|e| e.into_inner());
+    if BUILT_IN.contains(&tag) || registry.iter().any(|(t, _, _)| *t == tag) {
+        return Err(format!("rule tag '{}' is already registered", tag));
+    }
+    registry.push((tag, TypeId::of::<T>(), load_as::<T>));
+    Ok(())
+}
+
+/// Rebuilds a rule saved under `tag`.
+pub fn load(tag: &str, data: serde_json::Value) -> Result<Box<dyn Constraint>, String> {
+    let registry = REGISTRY.read().unwrap_or_else(|e| e.into_inner());
+    match registry.iter().find(|(t, _, _)| *t == tag) {
+        Some((_, _, loader)) => loader(data),
+        None => Err(format!("unknown rule tag '{}'", tag)),
+    }
+}
+
+/// A rule known only through `Constraint`, saved as its tag and its own data.
+pub struct CustomRule {
+    rule: Box<dyn Constraint>,
+    index: usize,
+}
+
+#[derive(Deserialize, Serialize)]
+struct SavedRule {
+    tag: String,
+    data: serde_json::Value,
+    index: usize,
+}
+
+impl CustomRule {
+    /// Fails unless `rule.tag()` was registered for `T`, so that the saved rule can be loaded back.
+    pub fn new<T: Constraint + 'static>(rule: T, index: usize) -> Result<Self, String> {
+        let tag = rule.tag();
+        let registry = REGISTRY.read().unwrap_or_else(|e| e.into_inner());
+        match registry.iter().find(|(t, _, _)| *t == tag) {
+            Some((_, id, _)) if *id == TypeId::of::<T>() => {}
+            Some(_) => return Err(format!("rule tag '{}' is registered for another type", tag)),
+            None => return Err(format!("rule tag '{}' is not registered", tag)),
+        }
+        Ok(CustomRule {
+            rule: Box::new(rule),
+            index,
+        })
+    }
+
+    pub fn get(&self) -> &dyn Constraint {
+        self.rule.as_ref()
+    }
+
+    pub fn get_index(&self) -> usize {
+        self.index
+    }
+}
+
+impl Clone for CustomRule {
+    fn clone(&self) -> Self {
+        CustomRule {
+            rule: self.rule.clone_box(),
+            index: self.index,
+        }
+    }
+}
+
+impl fmt::Debug for CustomRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {:?}", self.rule.tag(), self.rule)
+    }
+}
+
+impl Serialize for CustomRule {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let saved = SavedRule {
+            tag: self.rule.tag().to_string(),
+            data: self.rule.to_value().map_err(S::Error::custom)?,
+            index: self.index,
+        };
+        saved.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for CustomRule {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let saved = SavedRule::deserialize(deserializer)?;
+        Ok(CustomRule {
+            rule: load(&saved.tag, saved.data).map_err(D::Error::custom)?,
+            index: saved.index,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::game::Game;
+    use crate::logic::rules::Rule;
+    use crate::logic::solver::Solver;
+
+    /// Two cells that must hold different digits.
+    #[derive(Deserialize, Serialize, Clone, Debug)]
+    struct Differ {
+        positions: Vec<Position>,
+    }
+
+    impl Constraint for Differ {
+        fn tag(&self) -> &'static str {
+            "differ"
+        }
+
+        fn positions(&self) -> Vec<Position> {
+            self.positions.clone()
+        }
+
+        fn check(&self, board: &Board) -> RuleCheckResult {
+            let values: Vec<usize> = self.positions.iter().map(|&p| board.get_value(p)).collect();
+            if values[0] > 0 && values[0] == values[1] {
+                RuleCheckResult::Critical(String::from("(differ): equal digits"))
+            } else {
+                RuleCheckResult::Ok
+            }
+        }
+
+        fn propagate(&self, domains: &mut Domains) -> Option<bool> {
+            let (a, b) = (self.positions[0], self.positions[1]);
+            let mut changed = false;
+            for (from, to) in [(a, b), (b, a)] {
+                let domain = domains.get(from);
+                if domain.len() == 1 {
+                    changed |= domains.remove(to, domain.min()?)?;
+                }
+            }
+            Some(changed)
+        }
+    }
+
+    macro_rules! wrapper {
+        ($($name:ident => $tag:literal),*) => {
+            $(
+                #[derive(Deserialize, Serialize, Clone, Debug)]
+                struct $name(Differ);
+
+                impl Constraint for $name {
+                    fn tag(&self) -> &'static str {
+                        $tag
+                    }
+
+                    fn positions(&self) -> Vec<Position> {
+                        self.0.positions()
+                    }
+
+                    fn check(&self, board: &Board) -> RuleCheckResult {
+                        self.0.check(board)
+                    }
+                }
+            )*
+        };
+    }
+
+    wrapper!(Unregistered => "unregistered", Impostor => "differ", Shadow => "sum");
+
+    /// The registry is shared by all tests, so `Differ` is registered once by whichever runs first.
+    fn differ() -> Differ {
+        let _ = register::<Differ>("differ");
+        Differ {
+            positions: vec![
+                Position::new(1, 1, 9).unwrap(),
+                Position::new(5, 5, 9).unwrap(),
+            ],
+        }
+    }
+
+    #[test]
+    fn register_rejects_taken_tags() {
+        differ();
+        assert!(register::<Differ>("differ").is_err());
+        assert!(register::<Shadow>("sum").is_err());
+    }
+
+    #[test]
+    fn custom_rules_need_their_tag_registered_for_their_type() {
+        assert!(CustomRule::new(differ(), 0).is_ok());
+        assert!(CustomRule::new(Unregistered(differ()), 0).is_err());
+        assert!(CustomRule::new(Impostor(differ()), 0).is_err());
+        assert!(CustomRule::new(Shadow(differ()), 0).is_err());
+    }
+
+    #[test]
+    fn custom_rules_load_back_through_the_registry() {
+        let rule = Rule::Custom(CustomRule::new(differ(), 3).unwrap());
+        let saved = serde_json::to_string(&rule).unwrap();
+
+        let Rule::Custom(loaded) = serde_json::from_str::<Rule>(&saved).unwrap() else {
+            panic!("loaded a built-in rule from {}", saved);
+        };
+        assert_eq!(loaded.get().tag(), "differ");
+        assert_eq!(loaded.get().positions(), differ().positions);
+        assert_eq!(loaded.get_index(), 3);
+
+        let unknown = saved.replace("differ", "unregistered");
+        assert!(serde_json::from_str::<Rule>(&unknown).is_err());
+    }
+
+    #[test]
+    fn custom_rules_propagate_in_the_solver() {
+        let (a, b) = (
+            Position::new(1, 1, 9).unwrap(),
+            Position::new(5, 5, 9).unwrap(),
+        );
+        let mut game = Game::new(9, 3, 3).unwrap();
+        game.add_rule(Rule::Custom(CustomRule::new(differ(), 27).unwrap()));
+        game.set_value(a, 4);
+
+        let solver = Solver::new(game, false);
+        assert!(!solver.get_options(b).contains(&4));
+    }
+}
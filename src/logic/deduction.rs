@@ -0,0 +1,1023 @@
+use super::board::{Board, Position};
+use super::digits::DigitSet;
+use super::game::Game;
+use super::rules::{GlobalRule, Rule, RuleCheckResult};
+use std::collections::VecDeque;
+use std::fmt::{self, Display};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Technique {
+    NakedSingle,
+    HiddenSingle,
+    RuleConflict,
+    NakedPair,
+    HiddenPair,
+    NakedTriple,
+    HiddenTriple,
+    LockedCandidates,
+    XWing,
+    Swordfish,
+    XYWing,
+    SimpleColoring,
+}
+
+impl Technique {
+    /// Position of the technique in the order the logical solver tries them, starting at 1.
+    pub const fn level(self) -> usize {
+        self as usize + 1
+    }
+}
+
+impl Display for Technique {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Technique::NakedSingle => "Naked single",
+            Technique::HiddenSingle => "Hidden single",
+            Technique::RuleConflict => "Rule conflict",
+            Technique::NakedPair => "Naked pair",
+            Technique::HiddenPair => "Hidden pair",
+            Technique::NakedTriple => "Naked triple",
+            Technique::HiddenTriple => "Hidden triple",
+            Technique::LockedCandidates => "Pointing/claiming",
+            Technique::XWing => "X-Wing",
+            Technique::Swordfish => "Swordfish",
+            Technique::XYWing => "XY-Wing",
+            Technique::SimpleColoring => "Simple coloring",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Step {
+    pub technique: Technique,
+    pub placements: Vec<(Position, usize)>,
+    pub eliminations: Vec<(Position, usize)>,
+    pub cells: Vec<Position>,
+    pub description: String,
+}
+
+impl Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.technique, self.description)
+    }
+}
+
+type Finder = fn(&LogicalSolver) -> Option<Step>;
+
+const TECHNIQUES: [Finder; 12] = [
+    LogicalSolver::naked_single,
+    LogicalSolver::hidden_single,
+    LogicalSolver::rule_conflict,
+    |s| s.naked_subset(2),
+    |s| s.hidden_subset(2),
+    |s| s.naked_subset(3),
+    |s| s.hidden_subset(3),
+    LogicalSolver::locked_candidates,
+    |s| s.fish(2),
+    |s| s.fish(3),
+    LogicalSolver::xy_wing,
+    LogicalSolver::simple_coloring,
+];
+
+pub struct LogicalSolver {
+    side: usize,
+    digits: usize,
+    board: Board,
+    candidates: Vec<DigitSet>,
+    groups: Vec<Vec<usize>>,
+    cell_groups: Vec<Vec<usize>>,
+    peers: Vec<Vec<usize>>,
+    rules: Vec<Rule>,
+    global_rules: Vec<GlobalRule>,
+    steps: Vec<Step>,
+}
+
+impl LogicalSolver {
+    pub fn new(game: &Game) -> Self {
+        let board = game.board();
+        let (side, digits) = (board.get_side(), board.get_digits());
+        let cells = side * side;
+
+        let mut groups: Vec<Vec<usize>> = vec![];
+        let mut rules = vec![];
+        for rule in game.rules() {
+            match rule {
+                Rule::Permutation(r) if r.positions.len() == digits => {
+                    groups.push(r.positions.iter().map(|pos| pos.index(side)).collect());
+                }
+                rule => rules.push(rule),
+            }
+        }
+
+        let mut cell_groups = vec![vec![]; cells];
+        for (g, group) in groups.iter().enumerate() {
+            for &cell in group {
+                cell_groups[cell].push(g);
+            }
+        }
+
+        let mut peers = vec![vec![]; cells];
+        for (cell, cell_peers) in peers.iter_mut().enumerate() {
+            for &g in &cell_groups[cell] {
+                for &other in &groups[g] {
+                    if other != cell && !cell_peers.contains(&other) {
+                        cell_peers.push(other);
+                    }
+                }
+            }
+        }
+
+        let global_rules = game.global_rules();
+        for global in global_rules.iter().filter(|global| global.is_distinct()) {
+            for (a, b) in global.pairs(&board, &rules) {
+                let (a, b) = (a.index(side), b.index(side));
+                if !peers[a].contains(&b) {
+                    peers[a].push(b);
+                    peers[b].push(a);
+                }
+            }
+        }
+
+        let mut candidates = vec![DigitSet::empty(); cells];
+        for pos in board.cells() {
+            candidates[pos.index(side)] = DigitSet::full(digits);
+        }
+
+        let mut solver = LogicalSolver {
+            side,
+            digits,
+            board: board.clone(),
+            candidates,
+            groups,
+            cell_groups,
+            peers,
+            rules,
+            global_rules,
+            steps: vec![],
+        };
+
+        for pos in board.cells() {
+            let value = board.get_value(pos);
+            if value > 0 {
+                solver.assign(pos.index(side), value);
+            }
+        }
+
+        solver
+    }
+
+    pub fn get_candidates(&self, pos: Position) -> Vec<usize> {
+        self.candidates[pos.index(self.side)].to_vec()
+    }
+
+    pub fn board(&self) -> Board {
+        self.board.clone()
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    pub fn is_solved(&self) -> bool {
+        self.board
+            .cells()
+            .into_iter()
+            .all(|pos| self.board.get_value(pos) > 0)
+    }
+
+    pub fn next_step(&self) -> Option<Step> {
+        TECHNIQUES.iter().find_map(|find| find(self))
+    }
+
+    pub fn apply(&mut self, step: &Step) {
+        for &(pos, digit) in &step.placements {
+            self.assign(pos.index(self.side), digit);
+        }
+        for &(pos, digit) in &step.eliminations {
+            self.candidates[pos.index(self.side)].remove(digit);
+        }
+        self.steps.push(step.clone());
+    }
+
+    pub fn eliminate(&mut self, pos: Position, digit: usize) {
+        self.candidates[pos.index(self.side)].remove(digit);
+    }
+
+    pub fn solve(&mut self) -> bool {
+        while let Some(step) = self.next_step() {
+            self.apply(&step);
+        }
+        self.is_solved()
+    }
+
+    fn assign(&mut self, cell: usize, digit: usize) {
+        self.board.set_value(self.position(cell), digit);
+        self.candidates[cell] = DigitSet::empty();
+        for &peer in &self.peers[cell] {
+            self.candidates[peer].remove(digit);
+        }
+    }
+
+    fn position(&self, cell: usize) -> Position {
+        Position::from_index(cell, self.side).unwrap()
+    }
+
+    fn cell_name(&self, cell: usize) -> String {
+        let pos = self.position(cell);
+        format!("r{}c{}", pos.row(), pos.col())
+    }
+
+    fn cell_list(&self, cells: &[usize]) -> String {
+        cells
+            .iter()
+            .map(|&c| self.cell_name(c))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn line_of(&self, group: usize) -> Option<(bool, usize)> {
+        let positions: Vec<Position> = self.groups[group]
+            .iter()
+            .map(|&c| self.position(c))
+            .collect();
+        let first = positions[0];
+        if positions.iter().all(|p| p.row() == first.row()) {
+            Some((true, first.row()))
+        } else if positions.iter().all(|p| p.col() == first.col()) {
+            Some((false, first.col()))
+        } else {
+            None
+        }
+    }
+
+    fn group_name(&self, group: usize) -> String {
+        match self.line_of(group) {
+            Some((true, row)) => format!("row {}", row),
+            Some((false, col)) => format!("column {}", col),
+            None => format!("region at {}", self.cell_name(self.groups[group][0])),
+        }
+    }
+
+    fn is_empty_cell(&self, cell: usize) -> bool {
+        self.board.get_value(self.position(cell)) == 0
+    }
+
+    fn sees(&self, a: usize, b: usize) -> bool {
+        self.peers[a].contains(&b)
+    }
+
+    fn cells_with(&self, group: usize, digit: usize) -> Vec<usize> {
+        self.groups[group]
+            .iter()
+            .copied()
+            .filter(|&c| self.candidates[c].contains(digit))
+            .collect()
+    }
+
+    fn is_placed_in(&self, group: usize, digit: usize) -> bool {
+        self.groups[group]
+            .iter()
+            .any(|&c| self.board.get_value(self.position(c)) == digit)
+    }
+
+    fn step(
+        &self,
+        technique: Technique,
+        placements: Vec<(usize, usize)>,
+        eliminations: Vec<(usize, usize)>,
+        cells: &[usize],
+        description: String,
+    ) -> Option<Step> {
+        if placements.is_empty() && eliminations.is_empty() {
+            return None;
+        }
+
+        let mut eliminations: Vec<(Position, usize)> = eliminations
+            .into_iter()
+            .map(|(c, d)| (self.position(c), d))
+            .collect();
+        eliminations.sort_by_key(|&(pos, d)| (pos.index(self.side), d));
+        eliminations.dedup();
+
+        Some(Step {
+            technique,
+            placements: placements
+                .into_iter()
+                .map(|(c, d)| (self.position(c), d))
+                .collect(),
+            eliminations,
+            cells: cells.iter().map(|&c| self.position(c)).collect(),
+            description,
+        })
+    }
+
+    fn naked_single(&self) -> Option<Step> {
+        (0..self.candidates.len())
+            .filter(|&cell| self.is_empty_cell(cell) && self.candidates[cell].len() == 1)
+            .find_map(|cell| {
+                let digit = self.candidates[cell].min()?;
+                self.step(
+                    Technique::NakedSingle,
+                    vec![(cell, digit)],
+                    vec![],
+                    &[cell],
+                    format!(
+                        "{} can only be {}, every other digit is already seen",
+                        self.cell_name(cell),
+                        digit
+                    ),
+                )
+            })
+    }
+
+    fn hidden_single(&self) -> Option<Step> {
+        for group in 0..self.groups.len() {
+            for digit in 1..=self.digits {
+                if self.is_placed_in(group, digit) {
+                    continue;
+                }
+                if let [cell] = self.cells_with(group, digit)[..] {
+                    return self.step(
+                        Technique::HiddenSingle,
+                        vec![(cell, digit)],
+                        vec![],
+                        &[cell],
+                        format!(
+                            "{} is the only place for {} in {}",
+                            self.cell_name(cell),
+                            digit,
+                            self.group_name(group)
+                        ),
+                    );
+                }
+            }
+        }
+        None
+    }
+
+    fn rule_conflict(&self) -> Option<Step> {
+        let found = self
+            .rules
+            .iter()
+            .find_map(|rule| self.conflict(&rule.get_positions(), |board| rule.check(board)));
+        if found.is_some() {
+            return found;
+        }
+
+        for global in &self.global_rules {
+            for (a, b) in global.pairs(&self.board, &self.rules) {
+                let found = self.conflict(&[a, b], |board| global.check_pair(board, a, b));
+                if found.is_some() {
+                    return found;
+                }
+            }
+        }
+        None
+    }
+
+    fn conflict(
+        &self,
+        positions: &[Position],
+        check: impl Fn(&Board) -> RuleCheckResult,
+    ) -> Option<Step> {
+        let mut board = self.board.clone();
+        let mut eliminations = vec![];
+        let mut reason = None;
+
+        for &pos in positions {
+            let cell = pos.index(self.side);
+            if !self.is_empty_cell(cell) {
+                continue;
+            }
+            for digit in self.candidates[cell].iter() {
+                board.set_value(pos, digit);
+                if let RuleCheckResult::Critical(msg) = check(&board) {
+                    eliminations.push((cell, digit));
+                    reason.get_or_insert(msg);
+                }
+            }
+            board.set_value(pos, 0);
+        }
+
+        let reason = reason?;
+        let removed = eliminations
+            .iter()
+            .map(|&(c, d)| format!("{} from {}", d, self.cell_name(c)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let cells: Vec<usize> = positions.iter().map(|p| p.index(self.side)).collect();
+
+        self.step(
+            Technique::RuleConflict,
+            vec![],
+            eliminations,
+            &cells,
+            format!(
+                "removing {} since each of them would break the rule {}",
+                removed, reason
+            ),
+        )
+    }
+
+    fn naked_subset(&self, size: usize) -> Option<Step> {
+        let technique = if size == 2 {
+            Technique::NakedPair
+        } else {
+            Technique::NakedTriple
+        };
+
+        for group in 0..self.groups.len() {
+            let open: Vec<usize> = self.groups[group]
+                .iter()
+                .copied()
+                .filter(|&c| (2..=size).contains(&self.candidates[c].len()))
+                .collect();
+
+            for subset in combinations(&open, size) {
+                let digits = subset
+                    .iter()
+                    .fold(DigitSet::empty(), |acc, &c| acc.union(self.candidates[c]));
+                if digits.len() != size {
+                    continue;
+                }
+
+                let eliminations: Vec<(usize, usize)> = self.groups[group]
+                    .iter()
+                    .filter(|c| !subset.contains(c))
+                    .flat_map(|&c| {
+                        self.candidates[c]
+                            .intersection(digits)
+                            .iter()
+                            .map(move |d| (c, d))
+                            .collect::<Vec<_>>()
+                    })
+                    .collect();
+
+                let step = self.step(
+                    technique,
+                    vec![],
+                    eliminations,
+                    &subset,
+                    format!(
+                        "{} can only hold {:?} in {}, so these digits are removed from the rest of it",
+                        self.cell_list(&subset),
+                        digits,
+                        self.group_name(group)
+                    ),
+                );
+                if step.is_some() {
+                    return step;
+                }
+            }
+        }
+        None
+    }
+
+    fn hidden_subset(&self, size: usize) -> Option<Step> {
+        let technique = if size == 2 {
+            Technique::HiddenPair
+        } else {
+            Technique::HiddenTriple
+        };
+
+        for group in 0..self.groups.len() {
+            let open: Vec<usize> = (1..=self.digits)
+                .filter(|&d| !self.is_placed_in(group, d))
+                .collect();
+
+            for subset in combinations(&open, size) {
+                let digits = DigitSet::from_digits(&subset);
+                let cells: Vec<usize> = self.groups[group]
+                    .iter()
+                    .copied()
+                    .filter(|&c| !self.candidates[c].intersection(digits).is_empty())
+                    .collect();
+                let covered = cells
+                    .iter()
+                    .fold(DigitSet::empty(), |acc, &c| acc.union(self.candidates[c]));
+                if cells.len() != size || covered.intersection(digits) != digits {
+                    continue;
+                }
+
+                let eliminations: Vec<(usize, usize)> = cells
+                    .iter()
+                    .flat_map(|&c| {
+                        self.candidates[c]
+                            .difference(digits)
+                            .iter()
+                            .map(move |d| (c, d))
+                            .collect::<Vec<_>>()
+                    })
+                    .collect();
+
+                let step = self.step(
+                    technique,
+                    vec![],
+                    eliminations,
+                    &cells,
+                    format!(
+                        "{:?} fit only in {} within {}, so other candidates are removed from these cells",
+                        digits,
+                        self.cell_list(&cells),
+                        self.group_name(group)
+                    ),
+                );
+                if step.is_some() {
+                    return step;
+                }
+            }
+        }
+        None
+    }
+
+    fn locked_candidates(&self) -> Option<Step> {
+        for a in 0..self.groups.len() {
+            for digit in 1..=self.digits {
+                let cells = self.cells_with(a, digit);
+                if cells.len() < 2 {
+                    continue;
+                }
+
+                for &b in &self.cell_groups[cells[0]] {
+                    if b == a || !cells.iter().all(|c| self.cell_groups[*c].contains(&b)) {
+                        continue;
+                    }
+
+                    let eliminations: Vec<(usize, usize)> = self
+                        .cells_with(b, digit)
+                        .into_iter()
+                        .filter(|c| !self.groups[a].contains(c))
+                        .map(|c| (c, digit))
+                        .collect();
+
+                    let kind = if self.line_of(a).is_some() {
+                        "claiming"
+                    } else {
+                        "pointing"
+                    };
+                    let step = self.step(
+                        Technique::LockedCandidates,
+                        vec![],
+                        eliminations,
+                        &cells,
+                        format!(
+                            "({}) in {} the digit {} is confined to {}, which also lie in {}, so {} is removed from the rest of {}",
+                            kind,
+                            self.group_name(a),
+                            digit,
+                            self.cell_list(&cells),
+                            self.group_name(b),
+                            digit,
+                            self.group_name(b)
+                        ),
+                    );
+                    if step.is_some() {
+                        return step;
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn fish(&self, size: usize) -> Option<Step> {
+        let technique = if size == 2 {
+            Technique::XWing
+        } else {
+            Technique::Swordfish
+        };
+
+        for by_rows in [true, false] {
+            let mut bases = vec![];
+            let mut covers = vec![vec![]; self.side + 1];
+            for g in 0..self.groups.len() {
+                match self.line_of(g) {
+                    Some((is_row, _)) if is_row == by_rows => bases.push(g),
+                    Some((_, coord)) => covers[coord].push(g),
+                    None => {}
+                }
+            }
+
+            for digit in 1..=self.digits {
+                let lines: Vec<usize> = bases
+                    .iter()
+                    .copied()
+                    .filter(|&g| (2..=size).contains(&self.cells_with(g, digit).len()))
+                    .collect();
+
+                for subset in combinations(&lines, size) {
+                    // on a canvas with several grids one line is split into groups that may overlap,
+                    // so the base groups have to lie on different lines to be disjoint
+                    let mut base_coords: Vec<usize> = subset
+                        .iter()
+                        .filter_map(|&g| self.line_of(g).map(|(_, coord)| coord))
+                        .collect();
+                    base_coords.sort();
+                    base_coords.dedup();
+                    if base_coords.len() != size {
+                        continue;
+                    }
+
+                    let cells: Vec<usize> = subset
+                        .iter()
+                        .flat_map(|&g| self.cells_with(g, digit))
+                        .collect();
+                    let mut coords: Vec<usize> = cells
+                        .iter()
+                        .map(|&c| {
+                            let pos = self.position(c);
+                            if by_rows {
+                                pos.col()
+                            } else {
+                                pos.row()
+                            }
+                        })
+                        .collect();
+                    coords.sort();
+                    coords.dedup();
+                    if coords.len() != size {
+                        continue;
+                    }
+
+                    // the cover for a line is a group on it that holds all the base cells there
+                    let cover_groups: Option<Vec<usize>> = coords
+                        .iter()
+                        .map(|&coord| {
+                            covers[coord].iter().copied().find(|&g| {
+                                cells.iter().all(|c| {
+                                    let pos = self.position(*c);
+                                    let line = if by_rows { pos.col() } else { pos.row() };
+                                    line != coord || self.groups[g].contains(c)
+                                })
+                            })
+                        })
+                        .collect();
+                    let Some(cover_groups) = cover_groups else {
+                        continue;
+                    };
+
+                    let eliminations: Vec<(usize, usize)> = cover_groups
+                        .iter()
+                        .flat_map(|&g| self.cells_with(g, digit))
+                        .filter(|c| !cells.contains(c))
+                        .map(|c| (c, digit))
+                        .collect();
+
+                    let step = self.step(
+                        technique,
+                        vec![],
+                        eliminations,
+                        &cells,
+                        format!(
+                            "in {} the digit {} is limited to {}, so it is removed from the rest of {}",
+                            subset
+                                .iter()
+                                .map(|&g| self.group_name(g))
+                                .collect::<Vec<_>>()
+                                .join(", "),
+                            digit,
+                            self.cell_list(&cells),
+                            cover_groups
+                                .iter()
+                                .map(|&g| self.group_name(g))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ),
+                    );
+                    if step.is_some() {
+                        return step;
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn xy_wing(&self) -> Option<Step> {
+        let bivalue = |c: usize| self.candidates[c].len() == 2;
+
+        for pivot in (0..self.candidates.len()).filter(|&c| bivalue(c)) {
+            let pivot_digits = self.candidates[pivot];
+            let wings: Vec<usize> = self.peers[pivot]
+                .iter()
+                .copied()
+                .filter(|&c| bivalue(c) && self.candidates[c].intersection(pivot_digits).len() == 1)
+                .collect();
+
+            for (i, &a) in wings.iter().enumerate() {
+                for &b in &wings[i + 1..] {
+                    let shared_a = self.candidates[a].intersection(pivot_digits);
+                    let shared_b = self.candidates[b].intersection(pivot_digits);
+                    let z_a = self.candidates[a].difference(pivot_digits);
+                    let z_b = self.candidates[b].difference(pivot_digits);
+                    if shared_a == shared_b || z_a != z_b {
+                        continue;
+                    }
+                    let Some(z) = z_a.min() else {
+                        continue;
+                    };
+
+                    let eliminations: Vec<(usize, usize)> = self.peers[a]
+                        .iter()
+                        .copied()
+                        .filter(|&c| c != b && self.sees(c, b) && self.candidates[c].contains(z))
+                        .map(|c| (c, z))
+                        .collect();
+
+                    let step = self.step(
+                        Technique::XYWing,
+                        vec![],
+                        eliminations,
+                        &[pivot, a, b],
+                        format!(
+                            "pivot {} {:?} with wings {} {:?} and {} {:?}: one wing must be {}, so {} is removed from cells seeing both wings",
+                            self.cell_name(pivot),
+                            pivot_digits,
+                            self.cell_name(a),
+                            self.candidates[a],
+                            self.cell_name(b),
+                            self.candidates[b],
+                            z,
+                            z
+                        ),
+                    );
+                    if step.is_some() {
+                        return step;
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn simple_coloring(&self) -> Option<Step> {
+        for digit in 1..=self.digits {
+            let mut links: Vec<Vec<usize>> = vec![vec![]; self.candidates.len()];
+            for g in 0..self.groups.len() {
+                if let [a, b] = self.cells_with(g, digit)[..] {
+                    links[a].push(b);
+                    links[b].push(a);
+                }
+            }
+
+            let mut color: Vec<Option<bool>> = vec![None; self.candidates.len()];
+            for start in 0..self.candidates.len() {
+                if links[start].is_empty() || color[start].is_some() {
+                    continue;
+                }
+
+                let mut chain = vec![];
+                let mut queue = VecDeque::from([start]);
+                color[start] = Some(true);
+                while let Some(cell) = queue.pop_front() {
+                    chain.push(cell);
+                    for &next in &links[cell] {
+                        if color[next].is_none() {
+                            color[next] = color[cell].map(|c| !c);
+                            queue.push_back(next);
+                        }
+                    }
+                }
+
+                let side_of = |shade: bool| -> Vec<usize> {
+                    chain
+                        .iter()
+                        .copied()
+                        .filter(|&c| color[c] == Some(shade))
+                        .collect()
+                };
+                let (on, off) = (side_of(true), side_of(false));
+
+                for shade in [&on, &off] {
+                    let clash = shade
+                        .iter()
+                        .enumerate()
+                        .any(|(i, &a)| shade[i + 1..].iter().any(|&b| self.sees(a, b)));
+                    if clash {
+                        let eliminations = shade.iter().map(|&c| (c, digit)).collect();
+                        return self.step(
+                            Technique::SimpleColoring,
+                            vec![],
+                            eliminations,
+                            &chain,
+                            format!(
+                                "two cells of the same color in the {} chain {} see each other, so {} is false in all of {}",
+                                digit,
+                                self.cell_list(&chain),
+                                digit,
+                                self.cell_list(shade)
+                            ),
+                        );
+                    }
+                }
+
+                let eliminations: Vec<(usize, usize)> = (0..self.candidates.len())
+                    .filter(|&c| !chain.contains(&c) && self.candidates[c].contains(digit))
+                    .filter(|&c| {
+                        on.iter().any(|&a| self.sees(c, a)) && off.iter().any(|&b| self.sees(c, b))
+                    })
+                    .map(|c| (c, digit))
+                    .collect();
+
+                let step = self.step(
+                    Technique::SimpleColoring,
+                    vec![],
+                    eliminations,
+                    &chain,
+                    format!(
+                        "one color of the {} chain {} must be true, so {} is removed from cells seeing both colors",
+                        digit,
+                        self.cell_list(&chain),
+                        digit
+                    ),
+                );
+                if step.is_some() {
+                    return step;
+                }
+            }
+        }
+        None
+    }
+}
+
+fn combinations(items: &[usize], size: usize) -> Vec<Vec<usize>> {
+    if size == 0 {
+        return vec![vec![]];
+    }
+    if items.len() < size {
+        return vec![];
+    }
+
+    let mut result = vec![];
+    for (i, &item) in items.iter().enumerate() {
+        for mut rest in combinations(&items[i + 1..], size - 1) {
+            rest.insert(0, item);
+            result.push(rest);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::rules::CageRule;
+
+    fn pos(row: usize, col: usize) -> Position {
+        Position::new(row, col, 9).unwrap()
+    }
+
+    fn empty() -> LogicalSolver {
+        LogicalSolver::new(&Game::new(9, 3, 3).unwrap())
+    }
+
+    /// Leaves only `digits` as candidates of the cell.
+    fn keep(solver: &mut LogicalSolver, row: usize, col: usize, digits: &[usize]) {
+        for digit in (1..=9).filter(|d| !digits.contains(d)) {
+            solver.eliminate(pos(row, col), digit);
+        }
+    }
+
+    /// Removes `digit` from every cell of the row except the listed columns.
+    fn confine_to_row(solver: &mut LogicalSolver, digit: usize, row: usize, cols: &[usize]) {
+        for col in (1..=9).filter(|c| !cols.contains(c)) {
+            solver.eliminate(pos(row, col), digit);
+        }
+    }
+
+    fn assert_finds(step: Option<Step>, technique: Technique, eliminated: &[(Position, usize)]) {
+        let step = step.unwrap();
+        assert_eq!(step.technique, technique, "{}", step);
+        for elimination in eliminated {
+            assert!(step.eliminations.contains(elimination), "{}", step);
+        }
+    }
+
+    #[test]
+    fn singles() {
+        let mut solver = empty();
+        keep(&mut solver, 5, 5, &[7]);
+        let step = solver.naked_single().unwrap();
+        assert_eq!(step.placements, [(pos(5, 5), 7)]);
+
+        let mut solver = empty();
+        confine_to_row(&mut solver, 5, 1, &[9]);
+        let step = solver.hidden_single().unwrap();
+        assert_eq!(step.placements, [(pos(1, 9), 5)]);
+    }
+
+    #[test]
+    fn rule_conflict() {
+        let mut game = Game::new(9, 3, 3).unwrap();
+        let cage = CageRule::new(vec![pos(1, 1), pos(1, 2)], Some(3), 27);
+        game.add_rule(Rule::Cage(cage));
+        let step = LogicalSolver::new(&game).rule_conflict();
+        assert_finds(step.clone(), Technique::RuleConflict, &[(pos(1, 1), 9)]);
+        assert!(!step.unwrap().eliminations.contains(&(pos(1, 1), 2)));
+    }
+
+    #[test]
+    fn naked_subsets() {
+        let mut solver = empty();
+        keep(&mut solver, 1, 1, &[1, 2]);
+        keep(&mut solver, 1, 2, &[1, 2]);
+        let pair = solver.naked_subset(2);
+        assert_finds(
+            pair,
+            Technique::NakedPair,
+            &[(pos(1, 9), 1), (pos(1, 9), 2)],
+        );
+
+        let mut solver = empty();
+        keep(&mut solver, 1, 1, &[1, 2]);
+        keep(&mut solver, 1, 2, &[2, 3]);
+        keep(&mut solver, 1, 3, &[1, 3]);
+        let triple = solver.naked_subset(3);
+        assert_finds(triple, Technique::NakedTriple, &[(pos(1, 9), 3)]);
+    }
+
+    #[test]
+    fn hidden_subsets() {
+        let mut solver = empty();
+        confine_to_row(&mut solver, 1, 1, &[1, 2]);
+        confine_to_row(&mut solver, 2, 1, &[1, 2]);
+        let pair = solver.hidden_subset(2);
+        assert_finds(
+            pair,
+            Technique::HiddenPair,
+            &[(pos(1, 1), 9), (pos(1, 2), 3)],
+        );
+
+        let mut solver = empty();
+        for digit in 1..=3 {
+            confine_to_row(&mut solver, digit, 1, &[1, 2, 3]);
+        }
+        let triple = solver.hidden_subset(3);
+        assert_finds(triple, Technique::HiddenTriple, &[(pos(1, 3), 4)]);
+    }
+
+    #[test]
+    fn locked_candidates() {
+        let mut solver = empty();
+        for row in 2..=3 {
+            for col in 1..=3 {
+                solver.eliminate(pos(row, col), 1);
+            }
+        }
+        let step = solver.locked_candidates();
+        assert_finds(
+            step,
+            Technique::LockedCandidates,
+            &[(pos(1, 4), 1), (pos(1, 9), 1)],
+        );
+    }
+
+    #[test]
+    fn fish() {
+        let mut solver = empty();
+        confine_to_row(&mut solver, 1, 1, &[1, 5]);
+        confine_to_row(&mut solver, 1, 5, &[1, 5]);
+        assert_finds(
+            solver.fish(2),
+            Technique::XWing,
+            &[(pos(2, 1), 1), (pos(9, 5), 1)],
+        );
+
+        let mut solver = empty();
+        confine_to_row(&mut solver, 1, 1, &[1, 4]);
+        confine_to_row(&mut solver, 1, 4, &[4, 7]);
+        confine_to_row(&mut solver, 1, 7, &[1, 7]);
+        assert!(solver.fish(2).is_none());
+        let eliminated = [(pos(2, 1), 1), (pos(5, 4), 1), (pos(9, 7), 1)];
+        assert_finds(solver.fish(3), Technique::Swordfish, &eliminated);
+    }
+
+    #[test]
+    fn xy_wing() {
+        let mut solver = empty();
+        keep(&mut solver, 1, 1, &[1, 2]);
+        keep(&mut solver, 1, 5, &[1, 3]);
+        keep(&mut solver, 5, 1, &[2, 3]);
+        let step = solver.xy_wing().unwrap();
+        assert_eq!(step.technique, Technique::XYWing);
+        assert_eq!(step.eliminations, [(pos(5, 5), 3)]);
+    }
+
+    #[test]
+    fn simple_coloring() {
+        // the chain r1c1 - r1c5 - r5c5 - r5c2 alternates colors, and r2c2 sees both ends
+        let mut solver = empty();
+        confine_to_row(&mut solver, 1, 1, &[1, 5]);
+        confine_to_row(&mut solver, 1, 5, &[2, 5]);
+        for row in [2, 3, 4, 6, 7, 8, 9] {
+            solver.eliminate(pos(row, 5), 1);
+        }
+        let step = solver.simple_coloring();
+        assert_finds(
+            step,
+            Technique::SimpleColoring,
+            &[(pos(2, 2), 1), (pos(4, 1), 1)],
+        );
+    }
+}
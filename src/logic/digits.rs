@@ -0,0 +1,88 @@
+use std::fmt;
+
+/// Largest digit a set can hold, which also caps the side of a board.
+pub const MAX_DIGIT: usize = 63;
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub struct DigitSet(u64);
+
+impl DigitSet {
+    pub fn empty() -> Self {
+        DigitSet(0)
+    }
+
+    pub fn full(side: usize) -> Self {
+        DigitSet(((1u64 << side) - 1) << 1)
+    }
+
+    pub fn single(digit: usize) -> Self {
+        DigitSet(1 << digit)
+    }
+
+    pub fn range(low: usize, high: usize) -> Self {
+        (low.max(1)..=high.min(63)).fold(DigitSet::empty(), |set, d| set.with(d))
+    }
+
+    pub fn from_digits(digits: &[usize]) -> Self {
+        digits.iter().fold(DigitSet::empty(), |set, &d| set.with(d))
+    }
+
+    pub fn contains(&self, digit: usize) -> bool {
+        digit < 64 && self.0 & (1 << digit) != 0
+    }
+
+    pub fn insert(&mut self, digit: usize) {
+        self.0 |= 1 << digit;
+    }
+
+    pub fn remove(&mut self, digit: usize) {
+        self.0 &= !(1 << digit);
+    }
+
+    pub fn with(self, digit: usize) -> Self {
+        DigitSet(self.0 | (1 << digit))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn union(self, other: DigitSet) -> Self {
+        DigitSet(self.0 | other.0)
+    }
+
+    pub fn intersection(self, other: DigitSet) -> Self {
+        DigitSet(self.0 & other.0)
+    }
+
+    pub fn difference(self, other: DigitSet) -> Self {
+        DigitSet(self.0 & !other.0)
+    }
+
+    pub fn min(&self) -> Option<usize> {
+        (!self.is_empty()).then(|| self.0.trailing_zeros() as usize)
+    }
+
+    pub fn max(&self) -> Option<usize> {
+        (!self.is_empty()).then(|| 63 - self.0.leading_zeros() as usize)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> {
+        let bits = self.0;
+        (1..64).filter(move |d| bits & (1 << d) != 0)
+    }
+
+    pub fn to_vec(&self) -> Vec<usize> {
+        self.iter().collect()
+    }
+}
+
+impl fmt::Debug for DigitSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
@@ -0,0 +1,439 @@
+use super::board::Position;
+use std::fmt::{self, Display};
+
+/// Integer expression over cell values, e.g. `2 * r3c3 - abs(r1c1 - r1c2)`.
+#[derive(Clone, Debug)]
+pub enum Expr {
+    Number(isize),
+    Cell(Position),
+    Neg(Box<Expr>),
+    Abs(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn cells(&self, out: &mut Vec<Position>) {
+        match self {
+            Expr::Number(_) => {}
+            Expr::Cell(pos) => {
+                if !out.contains(pos) {
+                    out.push(*pos);
+                }
+            }
+            Expr::Neg(e) | Expr::Abs(e) => e.cells(out),
+            Expr::Add(a, b) | Expr::Sub(a, b) | Expr::Mul(a, b) => {
+                a.cells(out);
+                b.cells(out);
+            }
+        }
+    }
+
+    /// Smallest and largest value the expression can take when every cell stays within its bounds.
+    pub fn bounds(&self, cell: &impl Fn(Position) -> (isize, isize)) -> (isize, isize) {
+        match self {
+            Expr::Number(n) => (*n, *n),
+            Expr::Cell(pos) => cell(*pos),
+            Expr::Neg(e) => {
+                let (low, high) = e.bounds(cell);
+                (high.saturating_neg(), low.saturating_neg())
+            }
+            Expr::Abs(e) => {
+                let (low, high) = e.bounds(cell);
+                if low >= 0 {
+                    (low, high)
+                } else if high <= 0 {
+                    (high.saturating_neg(), low.saturating_neg())
+                } else {
+                    (0, high.max(low.saturating_neg()))
+                }
+            }
+            Expr::Add(a, b) => {
+                let ((a_low, a_high), (b_low, b_high)) = (a.bounds(cell), b.bounds(cell));
+                (a_low.saturating_add(b_low), a_high.saturating_add(b_high))
+            }
+            Expr::Sub(a, b) => {
+                let ((a_low, a_high), (b_low, b_high)) = (a.bounds(cell), b.bounds(cell));
+                (a_low.saturating_sub(b_high), a_high.saturating_sub(b_low))
+            }
+            Expr::Mul(a, b) => {
+                let ((a_low, a_high), (b_low, b_high)) = (a.bounds(cell), b.bounds(cell));
+                let products = [
+                    a_low.saturating_mul(b_low),
+                    a_low.saturating_mul(b_high),
+                    a_high.saturating_mul(b_low),
+                    a_high.saturating_mul(b_high),
+                ];
+                (
+                    *products.iter().min().unwrap(),
+                    *products.iter().max().unwrap(),
+                )
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    /// Whether some value of the left range and some value of the right range compare as required.
+    pub fn possible(&self, left: (isize, isize), right: (isize, isize)) -> bool {
+        let ((left_low, left_high), (right_low, right_high)) = (left, right);
+        match self {
+            Comparison::Equal => left_low <= right_high && right_low <= left_high,
+            Comparison::NotEqual => {
+                !(left_low == left_high && right_low == right_high && left_low == right_low)
+            }
+            Comparison::Less => left_low < right_high,
+            Comparison::LessOrEqual => left_low <= right_high,
+            Comparison::Greater => left_high > right_low,
+            Comparison::GreaterOrEqual => left_high >= right_low,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Condition {
+    Compare(Expr, Comparison, Expr),
+    Distinct(Vec<Position>),
+}
+
+impl Condition {
+    pub fn cells(&self) -> Vec<Position> {
+        match self {
+            Condition::Compare(left, _, right) => {
+                let mut cells = vec![];
+                left.cells(&mut cells);
+                right.cells(&mut cells);
+                cells
+            }
+            Condition::Distinct(cells) => cells.clone(),
+        }
+    }
+
+    /// Whether the constraint can still hold, given the bounds of every cell.
+    /// With every cell filled the bounds are exact, so this is the constraint itself.
+    pub fn possible(&self, cell: &impl Fn(Position) -> (isize, isize)) -> bool {
+        match self {
+            Condition::Compare(left, comparison, right) => {
+                comparison.possible(left.bounds(cell), right.bounds(cell))
+            }
+            Condition::Distinct(cells) => {
+                let fixed: Vec<isize> = cells
+                    .iter()
+                    .map(|&pos| cell(pos))
+                    .filter(|(low, high)| low == high)
+                    .map(|(low, _)| low)
+                    .collect();
+                (1..fixed.len()).all(|i| !fixed[..i].contains(&fixed[i]))
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            next: 0,
+        };
+        let constraint = parser.constraint()?;
+        match parser.peek() {
+            None => Ok(constraint),
+            Some(token) => Err(format!("unexpected {} after the constraint", token)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Number(isize),
+    Cell(usize, usize),
+    Word(String),
+    Symbol(&'static str),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "'{}'", n),
+            Token::Cell(row, col) => write!(f, "'r{}c{}'", row, col),
+            Token::Word(word) => write!(f, "'{}'", word),
+            Token::Symbol(symbol) => write!(f, "'{}'", symbol),
+        }
+    }
+}
+
+const SYMBOLS: [&str; 13] = [
+    "==", "!=", "<=", ">=", "<", ">", "=", "+", "-", "*", "(", ")", ",",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut rest = source.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        let length = if c.is_ascii_digit() {
+            let length = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let number = rest[..length]
+                .parse()
+                .map_err(|_| format!("number {} is too large", &rest[..length]))?;
+            tokens.push(Token::Number(number));
+            length
+        } else if c.is_ascii_alphabetic() {
+            let length = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            let word = rest[..length].to_lowercase();
+            tokens.push(cell(&word).unwrap_or(Token::Word(word)));
+            length
+        } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            tokens.push(Token::Symbol(match *symbol {
+                "=" => "==",
+                symbol => symbol,
+            }));
+            symbol.len()
+        } else {
+            return Err(format!("unexpected character '{}'", c));
+        };
+        rest = rest[length..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+fn cell(word: &str) -> Option<Token> {
+    let (row, col) = word.strip_prefix('r')?.split_once('c')?;
+    Some(Token::Cell(row.parse().ok()?, col.parse().ok()?))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: &'static str) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.next += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: &'static str) -> Result<(), String> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(match self.peek() {
+                Some(token) => format!("expected '{}', found {}", symbol, token),
+                None => format!("expected '{}' at the end", symbol),
+            })
+        }
+    }
+
+    fn constraint(&mut self) -> Result<Condition, String> {
+        if self.peek() == Some(&Token::Word(String::from("distinct"))) {
+            self.next += 1;
+            self.expect("(")?;
+            let mut cells = vec![self.cell()?];
+            while self.eat(",") {
+                let cell = self.cell()?;
+                if cells.contains(&cell) {
+                    return Err(format!("distinct lists {:?} twice", cell));
+                }
+                cells.push(cell);
+            }
+            self.expect(")")?;
+            if cells.len() < 2 {
+                return Err(String::from("distinct needs at least two cells"));
+            }
+            return Ok(Condition::Distinct(cells));
+        }
+
+        let left = self.expr()?;
+        let comparison = match self.advance() {
+            Some(Token::Symbol("==")) => Comparison::Equal,
+            Some(Token::Symbol("!=")) => Comparison::NotEqual,
+            Some(Token::Symbol("<")) => Comparison::Less,
+            Some(Token::Symbol("<=")) => Comparison::LessOrEqual,
+            Some(Token::Symbol(">")) => Comparison::Greater,
+            Some(Token::Symbol(">=")) => Comparison::GreaterOrEqual,
+            Some(token) => return Err(format!("expected a comparison, found {}", token)),
+            None => return Err(String::from("expected a comparison at the end")),
+        };
+        let right = self.expr()?;
+        Ok(Condition::Compare(left, comparison, right))
+    }
+
+    fn cell(&mut self) -> Result<Position, String> {
+        match self.advance() {
+            Some(Token::Cell(row, col)) => position(row, col),
+            Some(token) => Err(format!("expected a cell like r1c1, found {}", token)),
+            None => Err(String::from("expected a cell like r1c1 at the end")),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut expr = self.term()?;
+        loop {
+            if self.eat("+") {
+                expr = Expr::Add(Box::new(expr), Box::new(self.term()?));
+            } else if self.eat("-") {
+                expr = Expr::Sub(Box::new(expr), Box::new(self.term()?));
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut expr = self.factor()?;
+        while self.eat("*") {
+            expr = Expr::Mul(Box::new(expr), Box::new(self.factor()?));
+        }
+        Ok(expr)
+    }
+
+    fn factor(&mut self) -> Result<Expr, String> {
+        match self.advance() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Cell(row, col)) => Ok(Expr::Cell(position(row, col)?)),
+            Some(Token::Symbol("-")) => Ok(Expr::Neg(Box::new(self.factor()?))),
+            Some(Token::Symbol("(")) => {
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Token::Word(word)) if word == "abs" => {
+                self.expect("(")?;
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(Expr::Abs(Box::new(expr)))
+            }
+            Some(token) => Err(format!("expected a number, a cell or '(', found {}", token)),
+            None => Err(String::from("expression ends too early")),
+        }
+    }
+}
+
+/// The board size is not known while parsing; it is checked when the rule joins a game.
+fn position(row: usize, col: usize) -> Result<Position, String> {
+    Position::new(row, col, row.max(col))
+        .ok_or_else(|| String::from("cells are numbered from r1c1"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(source: &str, cells: &[(usize, usize, isize)]) -> isize {
+        let mut parser = Parser {
+            tokens: tokenize(source).unwrap(),
+            next: 0,
+        };
+        let bounds = parser.expr().unwrap().bounds(&|pos: Position| {
+            let &(_, _, value) = cells
+                .iter()
+                .find(|&&(row, col, _)| (row, col) == (pos.row(), pos.col()))
+                .unwrap();
+            (value, value)
+        });
+        assert_eq!(bounds.0, bounds.1);
+        bounds.0
+    }
+
+    #[test]
+    fn tokenizer_reads_cells_numbers_and_symbols() {
+        let tokens = tokenize("R1C2+ 10*abs(r3c4)= 5").unwrap();
+        assert_eq!(
+            tokens,
+            [
+                Token::Cell(1, 2),
+                Token::Symbol("+"),
+                Token::Number(10),
+                Token::Symbol("*"),
+                Token::Word(String::from("abs")),
+                Token::Symbol("("),
+                Token::Cell(3, 4),
+                Token::Symbol(")"),
+                Token::Symbol("=="),
+                Token::Number(5),
+            ]
+        );
+    }
+
+    #[test]
+    fn parser_follows_precedence() {
+        assert_eq!(value("1 + 2 * 3", &[]), 7);
+        assert_eq!(value("(1 + 2) * 3", &[]), 9);
+        assert_eq!(value("10 - 3 - 2", &[]), 5);
+        assert_eq!(
+            value("-r1c1 * 2 + abs(r1c2 - 9)", &[(1, 1, 4), (1, 2, 2)]),
+            -1
+        );
+    }
+
+    #[test]
+    fn parse_errors_name_the_problem() {
+        let cases = [
+            ("r1c1 # 2", "unexpected character '#'"),
+            ("99999999999999999999 == r1c1", "too large"),
+            ("r1c1 +", "ends too early"),
+            ("r1c1 r1c2", "expected a comparison, found 'r1c2'"),
+            ("r1c1 ==", "ends too early"),
+            ("r1c1 == 2 3", "unexpected '3' after the constraint"),
+            ("(r1c1 == 1", "expected ')', found '=='"),
+            ("abs r1c1 == 1", "expected '(', found 'r1c1'"),
+            ("foo == 1", "expected a number, a cell or '(', found 'foo'"),
+            ("r0c1 == 1", "numbered from r1c1"),
+            ("distinct(r1c1)", "at least two cells"),
+            ("distinct(r1c1, r1c1)", "twice"),
+            ("distinct(r1c1, 2)", "expected a cell like r1c1, found '2'"),
+        ];
+        for (source, message) in cases {
+            let error = source.parse::<Condition>().unwrap_err();
+            assert!(error.contains(message), "{}: {}", source, error);
+        }
+    }
+
+    #[test]
+    fn conditions_hold_within_bounds() {
+        let condition: Condition = "abs(r1c1 - r1c2) > 7".parse().unwrap();
+        let (r1c1, r1c2) = (
+            Position::new(1, 1, 9).unwrap(),
+            Position::new(1, 2, 9).unwrap(),
+        );
+        assert_eq!(condition.cells(), [r1c1, r1c2]);
+
+        let fixed = |a, b| move |pos: Position| if pos == r1c1 { a } else { b };
+        assert!(condition.possible(&fixed((1, 1), (1, 9))));
+        assert!(!condition.possible(&fixed((1, 1), (2, 8))));
+        assert!(condition.possible(&fixed((9, 9), (1, 9))));
+        assert!(!condition.possible(&fixed((5, 5), (1, 9))));
+    }
+}
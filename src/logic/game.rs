@@ -1,41 +1,71 @@
-use super::board::{Board, Position};
-use super::rules::{self, PermutationRule, Rule};
+use super::board::{Board, Corner, Outside, Position};
+use super::digits::MAX_DIGIT;
+use super::rules::{self, GlobalRule, LittleKillerRule, PermutationRule, Rule, SandwichRule};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Game {
     board: Board,
     rules: Vec<Rule>,
     base_rule_count: usize,
+    #[serde(default)]
+    global_rules: Vec<GlobalRule>,
+    /// Top-left cell of every grid when the board holds several of them, empty otherwise.
+    #[serde(default)]
+    grids: Vec<Position>,
 }
 
-impl Game {
-    pub fn new(side: usize, sub_rows: usize, sub_cols: usize) -> Self {
-        let mut game = Game {
-            board: Board::new(side),
-            rules: Vec::new(),
-            base_rule_count: 0,
+/// Well-known puzzles made of overlapping 9x9 grids.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    Samurai,
+    Twin,
+    Butterfly,
+}
+
+impl Layout {
+    pub fn game(&self) -> Game {
+        let (side, corners): (usize, &[(usize, usize)]) = match self {
+            Layout::Samurai => (21, &[(1, 1), (1, 13), (7, 7), (13, 1), (13, 13)]),
+            Layout::Twin => (15, &[(1, 1), (7, 7)]),
+            Layout::Butterfly => (12, &[(1, 1), (1, 4), (4, 1), (4, 4)]),
         };
+        let corners: Vec<Position> = corners
+            .iter()
+            .map(|&(row, col)| Position::new(row, col, side).unwrap())
+            .collect();
+        Game::with_grids(side, 3, 3, &corners).unwrap()
+    }
+}
 
-        let mut rc = 0;
-        for row in 1..=side {
-            let positions: Vec<Position> = (1..=side)
-                .filter_map(|col| Position::new(row, col))
-                .collect();
-            game.add_rule(Rule::Permutation(PermutationRule::new(positions, rc)));
-            rc += 1;
+impl std::str::FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "samurai" => Ok(Layout::Samurai),
+            "twin" => Ok(Layout::Twin),
+            "butterfly" => Ok(Layout::Butterfly),
+            other => Err(format!("unknown layout: {}", other)),
         }
+    }
+}
 
-        for col in 1..=side {
-            let positions: Vec<Position> = (1..=side)
-                .filter_map(|row| Position::new(row, col))
-                .collect();
-            game.add_rule(Rule::Permutation(PermutationRule::new(positions, rc)));
-            rc += 1;
+impl Game {
+    pub fn new(side: usize, sub_rows: usize, sub_cols: usize) -> Result<Self, String> {
+        check_digits(side)?;
+        if sub_rows * sub_cols != side {
+            return Err(format!(
+                "boxes of {}x{} do not hold {} digits",
+                sub_rows, sub_cols, side
+            ));
         }
 
+        let mut game = Game::with_lines(side);
+        let mut rc = game.base_rule_count;
+
         for sub_row in 0..side / sub_rows {
             for sub_col in 0..side / sub_cols {
                 let mut positions = Vec::new();
@@ -43,7 +73,7 @@ impl Game {
                     for col in 1..=sub_cols {
                         let pos_row = sub_row * sub_rows + row;
                         let pos_col = sub_col * sub_cols + col;
-                        if let Some(pos) = Position::new(pos_row, pos_col) {
+                        if let Some(pos) = Position::new(pos_row, pos_col, side) {
                             positions.push(pos);
                         }
                     }
@@ -53,37 +83,244 @@ impl Game {
             }
         }
 
+        game.base_rule_count = rc;
+        Ok(game)
+    }
+
+    /// Builds a jigsaw game: `regions[row][col]` is the region (0..side) of every cell.
+    pub fn with_regions(side: usize, regions: &[Vec<usize>]) -> Result<Self, String> {
+        check_digits(side)?;
+        if regions.len() != side || regions.iter().any(|line| line.len() != side) {
+            return Err(format!("region map should be {}x{}", side, side));
+        }
+
+        let mut cells: Vec<Vec<Position>> = vec![vec![]; side];
+        for (row, line) in regions.iter().enumerate() {
+            for (col, &region) in line.iter().enumerate() {
+                if region >= side {
+                    return Err(format!(
+                        "region {} is out of range, expected less than {}",
+                        region, side
+                    ));
+                }
+                cells[region].push(Position::new(row + 1, col + 1, side).unwrap());
+            }
+        }
+
+        for (region, positions) in cells.iter().enumerate() {
+            if positions.len() != side {
+                return Err(format!(
+                    "region {} has {} cells instead of {}",
+                    region,
+                    positions.len(),
+                    side
+                ));
+            }
+            if !is_connected(positions) {
+                return Err(format!("region {} is not connected", region));
+            }
+        }
+
+        let mut game = Game::with_lines(side);
+        let mut rc = game.base_rule_count;
+        for positions in cells {
+            game.add_rule(Rule::Permutation(PermutationRule::new(positions, rc)));
+            rc += 1;
+        }
+
+        game.base_rule_count = rc;
+        Ok(game)
+    }
+
+    /// Builds a puzzle of overlapping grids on a `side`x`side` canvas. Every grid holds
+    /// `sub_rows * sub_cols` digits and starts at one of `corners`; cells outside all grids are blocked.
+    pub fn with_grids(
+        side: usize,
+        sub_rows: usize,
+        sub_cols: usize,
+        corners: &[Position],
+    ) -> Result<Self, String> {
+        let digits = sub_rows * sub_cols;
+        if corners.is_empty() || digits == 0 {
+            return Err(String::from("at least one grid with boxes is needed"));
+        }
+        check_digits(digits)?;
+
+        let mut grids: Vec<Vec<Vec<Position>>> = vec![];
+        for (i, corner) in corners.iter().enumerate() {
+            if corners[..i].contains(corner) {
+                return Err(format!("grid at {:?} is listed twice", corner));
+            }
+            if (corner.row() - 1) % sub_rows != 0 || (corner.col() - 1) % sub_cols != 0 {
+                return Err(format!(
+                    "grid at {:?} does not line up with the {}x{} boxes",
+                    corner, sub_rows, sub_cols
+                ));
+            }
+            let grid: Vec<Vec<Position>> = (0..digits as isize)
+                .map(|row| {
+                    (0..digits as isize)
+                        .filter_map(|col| corner.offset(row, col, side))
+                        .collect()
+                })
+                .collect();
+            if grid.iter().any(|line| line.len() != digits) {
+                return Err(format!(
+                    "grid at {:?} does not fit on the {}x{} canvas",
+                    corner, side, side
+                ));
+            }
+            grids.push(grid);
+        }
+
+        let blocked: Vec<Position> = Board::new(side)
+            .cells()
+            .into_iter()
+            .filter(|pos| !grids.iter().flatten().flatten().any(|cell| cell == pos))
+            .collect();
+
+        let mut game = Game {
+            board: Board::with_blocked(side, digits, &blocked),
+            rules: Vec::new(),
+            base_rule_count: 0,
+            global_rules: Vec::new(),
+            grids: corners.to_vec(),
+        };
+
+        let mut rc = 0;
+        for grid in &grids {
+            for row in grid {
+                game.add_rule(Rule::Permutation(PermutationRule::new(row.clone(), rc)));
+                rc += 1;
+            }
+            for col in 0..digits {
+                let positions = grid.iter().map(|row| row[col]).collect();
+                game.add_rule(Rule::Permutation(PermutationRule::new(positions, rc)));
+                rc += 1;
+            }
+        }
+
+        // boxes come last, as in a single grid; grids that overlap share theirs
+        let mut boxes: Vec<Vec<Position>> = vec![];
+        for grid in &grids {
+            for box_row in (0..digits).step_by(sub_rows) {
+                for box_col in (0..digits).step_by(sub_cols) {
+                    let positions: Vec<Position> = grid[box_row..box_row + sub_rows]
+                        .iter()
+                        .flat_map(|row| row[box_col..box_col + sub_cols].iter().copied())
+                        .collect();
+                    if !boxes.contains(&positions) {
+                        boxes.push(positions);
+                    }
+                }
+            }
+        }
+        for positions in boxes {
+            game.add_rule(Rule::Permutation(PermutationRule::new(positions, rc)));
+            rc += 1;
+        }
+
+        game.base_rule_count = rc;
+        Ok(game)
+    }
+
+    fn with_lines(side: usize) -> Self {
+        let mut game = Game {
+            board: Board::new(side),
+            rules: Vec::new(),
+            base_rule_count: 0,
+            global_rules: Vec::new(),
+            grids: Vec::new(),
+        };
+
+        let mut rc = 0;
+        for row in 1..=side {
+            let positions: Vec<Position> = (1..=side)
+                .filter_map(|col| Position::new(row, col, side))
+                .collect();
+            game.add_rule(Rule::Permutation(PermutationRule::new(positions, rc)));
+            rc += 1;
+        }
+
+        for col in 1..=side {
+            let positions: Vec<Position> = (1..=side)
+                .filter_map(|row| Position::new(row, col, side))
+                .collect();
+            game.add_rule(Rule::Permutation(PermutationRule::new(positions, rc)));
+            rc += 1;
+        }
+
         game.base_rule_count = rc;
         game
     }
 
     pub fn add_rule(&mut self, rule: Rule) {
         self.rules.push(rule);
+        self.sync_regions(self.rules.len() - 1);
+    }
+
+    pub fn add_position_to_rule(&mut self, index: usize, pos: Position) -> Result<(), String> {
+        self.edit_rule(index, |rule| rule.add_position(pos))
+    }
+
+    pub fn remove_position_from_rule(&mut self, index: usize, pos: Position) -> Result<(), String> {
+        self.edit_rule(index, |rule| rule.remove_position(pos))
     }
 
-    pub fn add_position_to_rule(&mut self, index: usize, pos: Position) {
-        self.rules[index + self.base_rule_count].add_position(pos);
+    /// Applies the edit only if the rule keeps a shape that `validate` accepts when loading.
+    fn edit_rule(&mut self, index: usize, edit: impl FnOnce(&mut Rule)) -> Result<(), String> {
+        let index = index + self.base_rule_count;
+        let mut rule = self.rules[index].clone();
+        edit(&mut rule);
+        rule.check_shape(self.get_digits())?;
+        self.rules[index] = rule;
+        self.sync_regions(index);
+        Ok(())
     }
 
-    pub fn remove_position_from_rule(&mut self, index: usize, pos: Position) {
-        self.rules[index + self.base_rule_count].remove_position(pos);
+    fn sync_regions(&mut self, rule: usize) {
+        if let Rule::Line(_) = self.rules[rule] {
+            let regions = self.get_regions();
+            if let Rule::Line(line) = &mut self.rules[rule] {
+                line.set_regions(&regions);
+            }
+        }
     }
 
     pub fn get_rule(&self, index: usize) -> Rule {
         self.rules[index + self.base_rule_count].clone()
     }
 
+    pub fn global_rules(&self) -> Vec<GlobalRule> {
+        self.global_rules.clone()
+    }
+
+    pub fn has_global_rule(&self, rule: GlobalRule) -> bool {
+        self.global_rules.contains(&rule)
+    }
+
+    pub fn set_global_rule(&mut self, rule: GlobalRule, enabled: bool) {
+        self.global_rules.retain(|&r| r != rule);
+        if enabled {
+            self.global_rules.push(rule);
+        }
+    }
+
     pub fn check_rules(&self) -> (Option<Vec<String>>, Option<Vec<String>>) {
         let mut violations = Vec::new();
         let mut pending: Vec<String> = Vec::new();
 
-        for rule in &self.rules {
-            let result = match rule {
-                Rule::Sum(r) => r.check(&self.board),
-                Rule::Permutation(r) => r.check(&self.board),
-                Rule::Relation(r) => r.check(&self.board),
-            };
+        let global_results = self
+            .global_rules
+            .iter()
+            .map(|rule| rule.check(&self.board, &self.rules));
+        let results = self
+            .rules
+            .iter()
+            .map(|rule| rule.check(&self.board))
+            .chain(global_results);
 
+        for result in results {
             match result {
                 rules::RuleCheckResult::Critical(msg) => {
                     violations.push(msg);
@@ -120,10 +357,81 @@ impl Game {
     pub fn load_from_file(filename: &str) -> Result<Self, Box<dyn std::error::Error>> {
         let path = Path::new(filename);
         let serialized = fs::read_to_string(path)?;
-        let game = serde_json::from_str(&serialized)?;
+        let mut game: Game = serde_json::from_str(&serialized)?;
+        game.validate()?;
+        for rule in 0..game.rules.len() {
+            game.sync_regions(rule);
+        }
         Ok(game)
     }
 
+    fn validate(&self) -> Result<(), String> {
+        let side = self.get_side();
+        if !self.board.is_consistent() {
+            return Err(format!("board does not match its declared side {}", side));
+        }
+        check_digits(self.board.get_digits())?;
+
+        for rule in &self.rules {
+            for pos in rule.get_positions() {
+                let on_canvas = Position::new(pos.row(), pos.col(), side) == Some(pos);
+                if on_canvas && self.board.is_blocked(pos) {
+                    return Err(format!("rule position {:?} is a blocked cell", pos));
+                }
+                if !on_canvas {
+                    return Err(format!(
+                        "rule position {:?} lies outside the {}x{} board",
+                        pos, side, side
+                    ));
+                }
+            }
+
+            rule.check_shape(self.board.get_digits())?;
+
+            if let Rule::Quad(r) = rule {
+                let corner = r.get_corner();
+                let digits = r.get_digits();
+                if Corner::new(corner.row(), corner.col(), side) != Some(corner)
+                    || r.positions != corner.cells()
+                    || digits.is_empty()
+                    || digits.len() > 4
+                    || digits
+                        .iter()
+                        .any(|d| !(1..=self.board.get_digits()).contains(d))
+                {
+                    return Err(format!(
+                        "quad at {:?} should list 1 to 4 digits around a corner inside the board",
+                        corner
+                    ));
+                }
+            }
+
+            let outside = match rule {
+                Rule::LittleKiller(r) => {
+                    let expected =
+                        LittleKillerRule::new(r.get_clue(), r.get_direction(), 0, side, 0);
+                    Some((r.get_clue(), expected.positions))
+                }
+                Rule::Sandwich(r) => {
+                    let expected = SandwichRule::new(r.get_clue(), 0, side, 0);
+                    Some((r.get_clue(), expected.positions))
+                }
+                _ => None,
+            };
+            if let Some((clue, expected)) = outside {
+                if Outside::new(clue.row(), clue.col(), side) != Some(clue)
+                    || rule.get_positions() != expected
+                {
+                    return Err(format!(
+                        "clue {:?} does not lie on the frame or match its cells",
+                        clue
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn board(&self) -> Board {
         self.board.clone()
     }
@@ -147,6 +455,32 @@ impl Game {
         self.base_rule_count
     }
 
+    pub fn get_digits(&self) -> usize {
+        self.board.get_digits()
+    }
+
+    /// Top-left cells of the grids on a canvas with several of them, empty for a single grid.
+    pub fn get_grids(&self) -> &[Position] {
+        &self.grids
+    }
+
+    /// Region of every cell, taken from the base rules that follow the rows and columns
+    /// of every grid. Blocked cells are left in region 0.
+    pub fn get_regions(&self) -> Vec<Vec<usize>> {
+        let side = self.get_side();
+        let mut regions = vec![vec![0; side]; side];
+
+        let lines = 2 * self.get_digits() * self.grids.len().max(1);
+        let boxes = self.rules[..self.base_rule_count].iter().skip(lines);
+        for (region, rule) in boxes.enumerate() {
+            for pos in rule.get_positions() {
+                let (row, col) = pos.coords();
+                regions[row][col] = region;
+            }
+        }
+        regions
+    }
+
     pub fn set_board(&mut self, board: Board) {
         self.board = board;
     }
@@ -172,3 +506,184 @@ impl Game {
         state
     }
 }
+
+fn check_digits(digits: usize) -> Result<(), String> {
+    if digits == 0 || digits > MAX_DIGIT {
+        return Err(format!(
+            "{} digits are not supported, use 1 to {}",
+            digits, MAX_DIGIT
+        ));
+    }
+    Ok(())
+}
+
+fn is_connected(positions: &[Position]) -> bool {
+    let Some(&first) = positions.first() else {
+        return true;
+    };
+
+    let mut reached = vec![first];
+    let mut next = 0;
+    while next < reached.len() {
+        let current = reached[next];
+        next += 1;
+        for &pos in positions {
+            if pos.is_adjacent(&current) && !reached.contains(&pos) {
+                reached.push(pos);
+            }
+        }
+    }
+    reached.len() == positions.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_rejects_sides_digit_sets_cannot_hold() {
+        assert!(Game::new(64, 8, 8).is_err());
+        assert!(Game::new(0, 0, 0).is_err());
+        assert!(Game::new(9, 2, 3).is_err());
+        assert_eq!(Game::new(25, 5, 5).unwrap().get_digits(), 25);
+    }
+
+    fn pos(row: usize, col: usize, side: usize) -> Position {
+        Position::new(row, col, side).unwrap()
+    }
+
+    /// Base rules (rows, columns and boxes of every grid) that hold the cell.
+    fn houses(game: &Game, pos: Position) -> usize {
+        game.rules()[..game.get_base_rule_count()]
+            .iter()
+            .filter(|rule| rule.get_positions().contains(&pos))
+            .count()
+    }
+
+    #[test]
+    fn layouts_share_boxes_between_overlapping_grids() {
+        // (layout, grids, boxes, open cells, a cell on two grids, a blocked cell)
+        let cases = [
+            (Layout::Samurai, 5, 41, 369, (7, 7), (1, 10)),
+            (Layout::Twin, 2, 17, 153, (9, 9), (1, 10)),
+            (Layout::Butterfly, 4, 16, 144, (1, 4), (0, 0)),
+        ];
+        for (layout, grids, boxes, cells, shared, blocked) in cases {
+            let game = layout.game();
+            let side = game.get_side();
+            assert_eq!(game.get_grids().len(), grids, "{:?}", layout);
+            assert_eq!(game.get_digits(), 9, "{:?}", layout);
+            assert_eq!(
+                game.get_base_rule_count(),
+                grids * 18 + boxes,
+                "{:?}",
+                layout
+            );
+            assert_eq!(game.board().cells().len(), cells, "{:?}", layout);
+
+            let regions = game.get_regions();
+            let last = regions.iter().flatten().max().unwrap();
+            assert_eq!(last + 1, boxes, "{:?}", layout);
+
+            assert_eq!(houses(&game, pos(1, 1, side)), 3, "{:?}", layout);
+            assert_eq!(
+                houses(&game, pos(shared.0, shared.1, side)),
+                5,
+                "{:?}",
+                layout
+            );
+            if let Some(blocked) = Position::new(blocked.0, blocked.1, side) {
+                assert!(game.board().is_blocked(blocked), "{:?}", layout);
+                assert_eq!(houses(&game, blocked), 0, "{:?}", layout);
+            }
+        }
+    }
+
+    #[test]
+    fn validate_rejects_blocked_cells() {
+        let game = Layout::Samurai.game();
+        let blocked = pos(1, 10, 21);
+
+        let mut saved = serde_json::to_value(&game).unwrap();
+        saved["board"]["board"][0][9] = 5.into();
+        let with_digit: Game = serde_json::from_value(saved).unwrap();
+        assert!(with_digit.validate().is_err());
+
+        let mut with_rule = game.clone();
+        with_rule.add_rule(Rule::Restriction(rules::RestrictionRule::new(
+            vec![blocked],
+            rules::DigitFilter::Even,
+            game.get_base_rule_count(),
+        )));
+        assert!(with_rule.validate().is_err());
+
+        assert!(game.validate().is_ok());
+    }
+
+    #[test]
+    fn multi_grid_games_survive_saving() {
+        let mut game = Layout::Twin.game();
+        game.set_value(pos(9, 9, 15), 4);
+        game.set_global_rule(GlobalRule::NonConsecutive, true);
+
+        let path = std::env::temp_dir().join(format!("twin-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        game.save_to_file(path).unwrap();
+        let loaded = Game::load_from_file(path);
+        fs::remove_file(path).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.get_digits(), 9);
+        assert_eq!(loaded.get_grids(), game.get_grids());
+        assert!(loaded.board().is_blocked(pos(1, 10, 15)));
+        assert_eq!(loaded.get_value(pos(9, 9, 15)), 4);
+        assert_eq!(loaded.global_rules(), [GlobalRule::NonConsecutive]);
+        assert_eq!(
+            serde_json::to_string(&loaded).unwrap(),
+            serde_json::to_string(&game).unwrap()
+        );
+    }
+
+    #[test]
+    fn games_saved_before_multi_grids_still_load() {
+        // plansza.json predates `digits`, `blocked`, `global_rules` and `grids`
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/plansza.json");
+        let loaded = Game::load_from_file(path).unwrap();
+        assert_eq!(loaded.get_digits(), 9);
+        assert!(loaded.get_grids().is_empty());
+        assert!(loaded.global_rules().is_empty());
+        assert_eq!(loaded.board().cells().len(), 81);
+        assert_eq!(loaded.get_value(pos(1, 4, 9)), 9);
+    }
+
+    #[test]
+    fn jigsaw_regions_are_validated() {
+        let jigsaw = vec![
+            vec![0, 0, 0, 1],
+            vec![0, 2, 1, 1],
+            vec![2, 2, 3, 1],
+            vec![2, 3, 3, 3],
+        ];
+        let game = Game::with_regions(4, &jigsaw).unwrap();
+        assert_eq!(game.get_regions(), jigsaw);
+
+        let short = jigsaw[..3].to_vec();
+        assert!(Game::with_regions(4, &short).is_err());
+
+        let mut out_of_range = jigsaw.clone();
+        out_of_range[0][0] = 4;
+        assert!(Game::with_regions(4, &out_of_range).is_err());
+
+        let mut uneven = jigsaw.clone();
+        uneven[0][3] = 0;
+        assert!(Game::with_regions(4, &uneven).is_err());
+
+        let split = vec![
+            vec![0, 0, 0, 1],
+            vec![1, 1, 1, 0],
+            vec![2, 2, 2, 2],
+            vec![3, 3, 3, 3],
+        ];
+        assert!(Game::with_regions(4, &split).is_err());
+    }
+}
@@ -1,4 +1,9 @@
 pub mod board;
+pub mod candidates;
+pub mod constraint;
+pub mod deduction;
+pub mod digits;
+pub mod expression;
 pub mod game;
 pub mod rules;
 pub mod solver;
@@ -1,11 +1,26 @@
-use super::board::{Board, Position};
+use super::board::{Board, Corner, Outside, Position};
+use super::constraint::{Constraint, CustomRule};
+use super::expression::Condition;
 use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub enum Rule {
     Sum(SumRule),
     Permutation(PermutationRule),
     Relation(RelationRule),
+    Cage(CageRule),
+    Thermometer(ThermometerRule),
+    Arrow(ArrowRule),
+    Kropki(KropkiRule),
+    XV(XVRule),
+    Restriction(RestrictionRule),
+    LittleKiller(LittleKillerRule),
+    Sandwich(SandwichRule),
+    Line(LineRule),
+    Quad(QuadRule),
+    Expression(ExpressionRule),
+    Custom(CustomRule),
 }
 
 #[derive(Deserialize, Serialize)]
@@ -16,20 +31,33 @@ pub enum RuleCheckResult {
 }
 
 impl Rule {
-    pub fn check(&self, board: &Board) -> RuleCheckResult {
+    /// The rule behind the variant, for code that treats every rule alike.
+    pub fn as_constraint(&self) -> &dyn Constraint {
         match self {
-            Rule::Sum(r) => r.check(board),
-            Rule::Permutation(r) => r.check(board),
-            Rule::Relation(r) => r.check(board),
+            Rule::Sum(r) => r,
+            Rule::Permutation(r) => r,
+            Rule::Relation(r) => r,
+            Rule::Cage(r) => r,
+            Rule::Thermometer(r) => r,
+            Rule::Arrow(r) => r,
+            Rule::Kropki(r) => r,
+            Rule::XV(r) => r,
+            Rule::Restriction(r) => r,
+            Rule::LittleKiller(r) => r,
+            Rule::Sandwich(r) => r,
+            Rule::Line(r) => r,
+            Rule::Quad(r) => r,
+            Rule::Expression(r) => r,
+            Rule::Custom(r) => r.get(),
         }
     }
 
+    pub fn check(&self, board: &Board) -> RuleCheckResult {
+        self.as_constraint().check(board)
+    }
+
     pub fn get_positions(&self) -> Vec<Position> {
-        match self {
-            Rule::Sum(r) => r.positions.clone(),
-            Rule::Permutation(r) => r.positions.clone(),
-            Rule::Relation(r) => r.positions.clone(),
-        }
+        self.as_constraint().positions()
     }
 
     pub fn add_position(&mut self, pos: Position) {
@@ -37,6 +65,17 @@ impl Rule {
             Rule::Sum(r) => r.positions.push(pos),
             Rule::Permutation(r) => r.positions.push(pos),
             Rule::Relation(r) => r.positions.push(pos),
+            Rule::Cage(r) => r.positions.push(pos),
+            Rule::Thermometer(r) => r.positions.push(pos),
+            Rule::Arrow(r) => r.positions.push(pos),
+            Rule::Kropki(r) => r.positions.push(pos),
+            Rule::XV(r) => r.positions.push(pos),
+            Rule::Restriction(r) => r.positions.push(pos),
+            // the cells follow from the clue and cannot be edited one by one
+            Rule::LittleKiller(_) | Rule::Sandwich(_) | Rule::Quad(_) | Rule::Expression(_) => {}
+            // a custom rule keeps its cells to itself
+            Rule::Custom(_) => {}
+            Rule::Line(r) => r.positions.push(pos),
         }
     }
 
@@ -45,7 +84,49 @@ impl Rule {
             Rule::Sum(r) => r.positions.retain(|&x| x != pos),
             Rule::Permutation(r) => r.positions.retain(|&x| x != pos),
             Rule::Relation(r) => r.positions.retain(|&x| x != pos),
+            Rule::Cage(r) => r.positions.retain(|&x| x != pos),
+            Rule::Thermometer(r) => r.positions.retain(|&x| x != pos),
+            Rule::Arrow(r) => r.positions.retain(|&x| x != pos),
+            Rule::Kropki(r) => r.positions.retain(|&x| x != pos),
+            Rule::XV(r) => r.positions.retain(|&x| x != pos),
+            Rule::Restriction(r) => r.positions.retain(|&x| x != pos),
+            Rule::LittleKiller(_) | Rule::Sandwich(_) | Rule::Quad(_) | Rule::Expression(_) => {}
+            Rule::Custom(_) => {}
+            Rule::Line(r) => r.positions.retain(|&x| x != pos),
+        }
+    }
+
+    /// Checks that the cells still form a valid marker, arrow or line, for edited and loaded rules alike.
+    pub fn check_shape(&self, digits: usize) -> Result<(), String> {
+        let positions = self.get_positions();
+        if let Rule::Arrow(r) = self {
+            if r.circle > 1 && digits > 9 {
+                return Err(format!(
+                    "a circle of {} cells is read as a decimal number, which needs digits up to 9",
+                    r.circle
+                ));
+            }
+        }
+        if let Rule::Kropki(_) | Rule::XV(_) = self {
+            if positions.len() > 2 {
+                return Err(String::from("a marker joins exactly two cells"));
+            }
+            if positions.len() == 2 && !positions[0].is_adjacent(&positions[1]) {
+                return Err(format!(
+                    "marker between {:?} and {:?} does not join adjacent cells",
+                    positions[0], positions[1]
+                ));
+            }
         }
+        if let Rule::Line(_) = self {
+            if let Some(pair) = positions.windows(2).find(|pair| !pair[0].touches(&pair[1])) {
+                return Err(format!(
+                    "line jumps from {:?} to {:?} instead of following neighbouring cells",
+                    pair[0], pair[1]
+                ));
+            }
+        }
+        Ok(())
     }
 
     pub fn get_index(&self) -> usize {
@@ -53,6 +134,18 @@ impl Rule {
             Rule::Sum(r) => r.index,
             Rule::Permutation(r) => r.index,
             Rule::Relation(r) => r.index,
+            Rule::Cage(r) => r.index,
+            Rule::Thermometer(r) => r.index,
+            Rule::Arrow(r) => r.index,
+            Rule::Kropki(r) => r.index,
+            Rule::XV(r) => r.index,
+            Rule::Restriction(r) => r.index,
+            Rule::LittleKiller(r) => r.index,
+            Rule::Sandwich(r) => r.index,
+            Rule::Line(r) => r.index,
+            Rule::Quad(r) => r.index,
+            Rule::Expression(r) => r.index,
+            Rule::Custom(r) => r.get_index(),
         }
     }
 }
@@ -105,7 +198,7 @@ impl PermutationRule {
         PermutationRule { positions, index }
     }
     pub fn check(&self, board: &Board) -> RuleCheckResult {
-        if (self.positions.len()) != board.get_side() {
+        if (self.positions.len()) != board.get_digits() {
             return RuleCheckResult::Ok;
         }
         let mut values: Vec<usize> = self
@@ -124,13 +217,13 @@ impl PermutationRule {
                 "(permutation): positions {:?} should be a permutation",
                 self.positions
             ))
-        } else if values.len() < board.get_side() {
+        } else if values.len() < board.get_digits() {
             RuleCheckResult::Unfulfilled(format!(
                 "(permutation): positions {:?} should be a permutation, (elements are missing)",
                 self.positions
             ))
         } else if unique_values.first() != Some(&1)
-            || unique_values.last() != Some(&board.get_side())
+            || unique_values.last() != Some(&board.get_digits())
         {
             RuleCheckResult::Critical(format!(
                 "(permutation): positions {:?} should be a permutation",
@@ -179,3 +272,1345 @@ impl RelationRule {
         }
     }
 }
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct CageRule {
+    pub positions: Vec<Position>,
+    sum: Option<usize>,
+    index: usize,
+}
+
+impl CageRule {
+    pub fn new(positions: Vec<Position>, sum: Option<usize>, index: usize) -> Self {
+        CageRule {
+            positions,
+            sum,
+            index,
+        }
+    }
+
+    pub fn get_sum(&self) -> Option<usize> {
+        self.sum
+    }
+
+    pub fn check(&self, board: &Board) -> RuleCheckResult {
+        let mut values: Vec<usize> = self
+            .positions
+            .iter()
+            .map(|&pos| board.get_value(pos))
+            .filter(|&x| x > 0)
+            .collect();
+        let filled = values.len();
+        let current_sum: usize = values.iter().sum();
+
+        values.sort();
+        values.dedup();
+        if values.len() != filled {
+            return RuleCheckResult::Critical(format!(
+                "(cage): positions {:?} should not repeat a digit",
+                self.positions
+            ));
+        }
+
+        if let Some(sum) = self.sum {
+            if current_sum > sum || (filled == self.positions.len() && current_sum != sum) {
+                return RuleCheckResult::Critical(format!(
+                    "(cage): positions {:?} should sum to {}, currently {}",
+                    self.positions, sum, current_sum
+                ));
+            }
+        }
+
+        if filled < self.positions.len() {
+            RuleCheckResult::Unfulfilled(format!(
+                "(cage): positions {:?} are not filled",
+                self.positions
+            ))
+        } else {
+            RuleCheckResult::Ok
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ThermometerRule {
+    pub positions: Vec<Position>,
+    index: usize,
+}
+
+impl ThermometerRule {
+    pub fn new(positions: Vec<Position>, index: usize) -> Self {
+        ThermometerRule { positions, index }
+    }
+
+    pub fn check(&self, board: &Board) -> RuleCheckResult {
+        let values: Vec<(usize, usize)> = self
+            .positions
+            .iter()
+            .map(|&pos| board.get_value(pos))
+            .enumerate()
+            .filter(|&(_, value)| value > 0)
+            .collect();
+
+        for pair in values.windows(2) {
+            let ((i, lower), (j, higher)) = (pair[0], pair[1]);
+            if higher < lower + (j - i) {
+                return RuleCheckResult::Critical(format!(
+                    "(thermometer): positions {:?} should strictly increase from the bulb",
+                    self.positions
+                ));
+            }
+        }
+
+        if values.len() < self.positions.len() {
+            RuleCheckResult::Unfulfilled(format!(
+                "(thermometer): positions {:?} are not filled",
+                self.positions
+            ))
+        } else {
+            RuleCheckResult::Ok
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ArrowRule {
+    pub positions: Vec<Position>,
+    circle: usize,
+    index: usize,
+}
+
+impl ArrowRule {
+    /// The first `circle` positions form the circle, read as a decimal number; the rest is the shaft.
+    /// A circle of several cells is only allowed while the digits stay below 10.
+    pub fn new(positions: Vec<Position>, circle: usize, index: usize) -> Self {
+        ArrowRule {
+            positions,
+            circle,
+            index,
+        }
+    }
+
+    pub fn get_circle(&self) -> usize {
+        self.circle
+    }
+
+    pub fn check(&self, board: &Board) -> RuleCheckResult {
+        if self.circle == 0 || self.positions.len() <= self.circle {
+            return RuleCheckResult::Ok;
+        }
+
+        let (circle, shaft) = self.positions.split_at(self.circle);
+        let circle_values: Vec<usize> = circle.iter().map(|&pos| board.get_value(pos)).collect();
+        let shaft_values: Vec<usize> = shaft.iter().map(|&pos| board.get_value(pos)).collect();
+        let shaft_sum: usize = shaft_values.iter().sum();
+
+        if circle_values.contains(&0) {
+            return RuleCheckResult::Unfulfilled(format!(
+                "(arrow): circle {:?} is not filled",
+                circle
+            ));
+        }
+
+        let target = circle_values.iter().fold(0, |acc, &d| acc * 10 + d);
+        let shaft_filled = !shaft_values.contains(&0);
+
+        if shaft_sum > target || (shaft_filled && shaft_sum != target) {
+            RuleCheckResult::Critical(format!(
+                "(arrow): positions {:?} should sum to {}, currently {}",
+                shaft, target, shaft_sum
+            ))
+        } else if !shaft_filled {
+            RuleCheckResult::Unfulfilled(format!("(arrow): positions {:?} are not filled", shaft))
+        } else {
+            RuleCheckResult::Ok
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KropkiColor {
+    White,
+    Black,
+}
+
+impl KropkiColor {
+    pub fn holds(&self, a: usize, b: usize) -> bool {
+        match self {
+            KropkiColor::White => a.abs_diff(b) == 1,
+            KropkiColor::Black => a == 2 * b || b == 2 * a,
+        }
+    }
+}
+
+impl Display for KropkiColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KropkiColor::White => write!(f, "white"),
+            KropkiColor::Black => write!(f, "black"),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct KropkiRule {
+    pub positions: Vec<Position>,
+    color: KropkiColor,
+    index: usize,
+}
+
+impl KropkiRule {
+    pub fn new(color: KropkiColor, index: usize) -> Self {
+        KropkiRule {
+            positions: vec![],
+            color,
+            index,
+        }
+    }
+
+    pub fn get_color(&self) -> KropkiColor {
+        self.color
+    }
+
+    pub fn check(&self, board: &Board) -> RuleCheckResult {
+        if self.positions.len() != 2 {
+            return RuleCheckResult::Ok;
+        }
+
+        let value1 = board.get_value(self.positions[0]);
+        let value2 = board.get_value(self.positions[1]);
+
+        if value1 == 0 || value2 == 0 {
+            RuleCheckResult::Unfulfilled(format!(
+                "(kropki): position {:?} or {:?} not filled",
+                self.positions[0], self.positions[1]
+            ))
+        } else if !self.color.holds(value1, value2) {
+            RuleCheckResult::Critical(format!(
+                "(kropki): {} dot between {:?} and {:?} is not satisfied",
+                self.color, self.positions[0], self.positions[1]
+            ))
+        } else {
+            RuleCheckResult::Ok
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XVMark {
+    X,
+    V,
+}
+
+impl XVMark {
+    pub fn sum(&self) -> usize {
+        match self {
+            XVMark::X => 10,
+            XVMark::V => 5,
+        }
+    }
+
+    pub fn holds(&self, a: usize, b: usize) -> bool {
+        a + b == self.sum()
+    }
+}
+
+impl Display for XVMark {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XVMark::X => write!(f, "X"),
+            XVMark::V => write!(f, "V"),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct XVRule {
+    pub positions: Vec<Position>,
+    mark: XVMark,
+    index: usize,
+}
+
+impl XVRule {
+    pub fn new(mark: XVMark, index: usize) -> Self {
+        XVRule {
+            positions: vec![],
+            mark,
+            index,
+        }
+    }
+
+    pub fn get_mark(&self) -> XVMark {
+        self.mark
+    }
+
+    pub fn check(&self, board: &Board) -> RuleCheckResult {
+        if self.positions.len() != 2 {
+            return RuleCheckResult::Ok;
+        }
+
+        let value1 = board.get_value(self.positions[0]);
+        let value2 = board.get_value(self.positions[1]);
+
+        if value1 == 0 || value2 == 0 {
+            RuleCheckResult::Unfulfilled(format!(
+                "(xv): position {:?} or {:?} not filled",
+                self.positions[0], self.positions[1]
+            ))
+        } else if !self.mark.holds(value1, value2) {
+            RuleCheckResult::Critical(format!(
+                "(xv): positions {:?} and {:?} should sum to {}",
+                self.positions[0],
+                self.positions[1],
+                self.mark.sum()
+            ))
+        } else {
+            RuleCheckResult::Ok
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub enum DigitFilter {
+    Even,
+    Odd,
+    Low,
+    High,
+    Digits(Vec<usize>),
+}
+
+impl DigitFilter {
+    /// Low and high digits are the ones below and above the middle of `1..=side`.
+    pub fn allows(&self, digit: usize, side: usize) -> bool {
+        let half = side.saturating_sub(1) / 2;
+        match self {
+            DigitFilter::Even => digit % 2 == 0,
+            DigitFilter::Odd => digit % 2 == 1,
+            DigitFilter::Low => digit <= half,
+            DigitFilter::High => digit > side - half,
+            DigitFilter::Digits(digits) => digits.contains(&digit),
+        }
+    }
+}
+
+impl Display for DigitFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DigitFilter::Even => write!(f, "even"),
+            DigitFilter::Odd => write!(f, "odd"),
+            DigitFilter::Low => write!(f, "low"),
+            DigitFilter::High => write!(f, "high"),
+            DigitFilter::Digits(digits) => write!(f, "{:?}", digits),
+        }
+    }
+}
+
+impl std::str::FromStr for DigitFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "even" => Ok(DigitFilter::Even),
+            "odd" => Ok(DigitFilter::Odd),
+            "low" => Ok(DigitFilter::Low),
+            "high" => Ok(DigitFilter::High),
+            other => other
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|part| !part.is_empty())
+                .map(|part| part.parse::<usize>())
+                .collect::<Result<Vec<usize>, _>>()
+                .ok()
+                .filter(|digits| !digits.is_empty())
+                .map(DigitFilter::Digits)
+                .ok_or_else(|| format!("unknown digit filter: {}", other)),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct RestrictionRule {
+    pub positions: Vec<Position>,
+    filter: DigitFilter,
+    index: usize,
+}
+
+impl RestrictionRule {
+    pub fn new(positions: Vec<Position>, filter: DigitFilter, index: usize) -> Self {
+        RestrictionRule {
+            positions,
+            filter,
+            index,
+        }
+    }
+
+    pub fn get_filter(&self) -> &DigitFilter {
+        &self.filter
+    }
+
+    pub fn check(&self, board: &Board) -> RuleCheckResult {
+        let side = board.get_digits();
+        for &pos in &self.positions {
+            let value = board.get_value(pos);
+            if value > 0 && !self.filter.allows(value, side) {
+                return RuleCheckResult::Critical(format!(
+                    "({}): position {:?} cannot hold {}",
+                    self.filter, pos, value
+                ));
+            }
+        }
+
+        if self.positions.iter().any(|&pos| board.get_value(pos) == 0) {
+            RuleCheckResult::Unfulfilled(format!(
+                "({}): positions {:?} are not filled",
+                self.filter, self.positions
+            ))
+        } else {
+            RuleCheckResult::Ok
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Diagonal {
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
+}
+
+impl Diagonal {
+    pub fn offset(&self) -> (isize, isize) {
+        match self {
+            Diagonal::UpLeft => (-1, -1),
+            Diagonal::UpRight => (-1, 1),
+            Diagonal::DownLeft => (1, -1),
+            Diagonal::DownRight => (1, 1),
+        }
+    }
+}
+
+impl Display for Diagonal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagonal::UpLeft => write!(f, "↖"),
+            Diagonal::UpRight => write!(f, "↗"),
+            Diagonal::DownLeft => write!(f, "↙"),
+            Diagonal::DownRight => write!(f, "↘"),
+        }
+    }
+}
+
+impl std::str::FromStr for Diagonal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "ul" | "↖" => Ok(Diagonal::UpLeft),
+            "ur" | "↗" => Ok(Diagonal::UpRight),
+            "dl" | "↙" => Ok(Diagonal::DownLeft),
+            "dr" | "↘" => Ok(Diagonal::DownRight),
+            other => Err(format!(
+                "unknown direction: {}, expected ul/ur/dl/dr",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct LittleKillerRule {
+    pub positions: Vec<Position>,
+    clue: Outside,
+    direction: Diagonal,
+    sum: usize,
+    index: usize,
+}
+
+impl LittleKillerRule {
+    /// Covers the cells on the diagonal starting next to `clue`; none when it misses the board.
+    pub fn new(clue: Outside, direction: Diagonal, sum: usize, side: usize, index: usize) -> Self {
+        let (rows, cols) = direction.offset();
+        LittleKillerRule {
+            positions: clue.ray(rows, cols, side),
+            clue,
+            direction,
+            sum,
+            index,
+        }
+    }
+
+    pub fn get_clue(&self) -> Outside {
+        self.clue
+    }
+
+    pub fn get_direction(&self) -> Diagonal {
+        self.direction
+    }
+
+    pub fn get_sum(&self) -> usize {
+        self.sum
+    }
+
+    pub fn check(&self, board: &Board) -> RuleCheckResult {
+        let values: Vec<usize> = self
+            .positions
+            .iter()
+            .map(|&pos| board.get_value(pos))
+            .collect();
+        let current_sum: usize = values.iter().sum();
+        let filled = !values.contains(&0);
+
+        if current_sum > self.sum || (filled && current_sum != self.sum) {
+            RuleCheckResult::Critical(format!(
+                "(little killer): positions {:?} should sum to {}, currently {}",
+                self.positions, self.sum, current_sum
+            ))
+        } else if !filled {
+            RuleCheckResult::Unfulfilled(format!(
+                "(little killer): positions {:?} are not filled",
+                self.positions
+            ))
+        } else {
+            RuleCheckResult::Ok
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct SandwichRule {
+    pub positions: Vec<Position>,
+    clue: Outside,
+    sum: usize,
+    index: usize,
+}
+
+impl SandwichRule {
+    /// Covers the row or column beside `clue`; none for a corner clue.
+    pub fn new(clue: Outside, sum: usize, side: usize, index: usize) -> Self {
+        let positions = clue
+            .inward(side)
+            .map(|(rows, cols)| clue.ray(rows, cols, side))
+            .unwrap_or_default();
+        SandwichRule {
+            positions,
+            clue,
+            sum,
+            index,
+        }
+    }
+
+    pub fn get_clue(&self) -> Outside {
+        self.clue
+    }
+
+    pub fn get_sum(&self) -> usize {
+        self.sum
+    }
+
+    /// The digits between the smallest and the largest digit (1 and 9 on a classic board) sum to the clue.
+    pub fn check(&self, board: &Board) -> RuleCheckResult {
+        let side = board.get_digits();
+        if self.positions.len() < 2 {
+            return RuleCheckResult::Ok;
+        }
+
+        let values: Vec<usize> = self
+            .positions
+            .iter()
+            .map(|&pos| board.get_value(pos))
+            .collect();
+        let low = values.iter().position(|&v| v == 1);
+        let high = values.iter().position(|&v| v == side);
+        let (Some(low), Some(high)) = (low, high) else {
+            return RuleCheckResult::Unfulfilled(format!(
+                "(sandwich): positions {:?} are missing 1 or {}",
+                self.positions, side
+            ));
+        };
+
+        let inner = &values[low.min(high) + 1..low.max(high)];
+        let current_sum: usize = inner.iter().sum();
+        let filled = !inner.contains(&0);
+
+        if current_sum > self.sum || (filled && current_sum != self.sum) {
+            RuleCheckResult::Critical(format!(
+                "(sandwich): digits between 1 and {} in {:?} should sum to {}, currently {}",
+                side, self.positions, self.sum, current_sum
+            ))
+        } else if !filled {
+            RuleCheckResult::Unfulfilled(format!(
+                "(sandwich): positions {:?} are not filled",
+                self.positions
+            ))
+        } else {
+            RuleCheckResult::Ok
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineKind {
+    Palindrome,
+    Renban,
+    Whispers,
+    RegionSum,
+}
+
+impl LineKind {
+    /// Smallest difference between neighbours on a whispers line, 5 on a classic board.
+    pub fn whisper_gap(side: usize) -> usize {
+        side.div_ceil(2)
+    }
+}
+
+impl Display for LineKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LineKind::Palindrome => write!(f, "palindrome"),
+            LineKind::Renban => write!(f, "renban"),
+            LineKind::Whispers => write!(f, "whispers"),
+            LineKind::RegionSum => write!(f, "region sum"),
+        }
+    }
+}
+
+impl std::str::FromStr for LineKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "palindrome" => Ok(LineKind::Palindrome),
+            "renban" => Ok(LineKind::Renban),
+            "whispers" => Ok(LineKind::Whispers),
+            "region sum" => Ok(LineKind::RegionSum),
+            other => Err(format!("unknown line: {}", other)),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct LineRule {
+    pub positions: Vec<Position>,
+    kind: LineKind,
+    #[serde(default)]
+    regions: Vec<usize>,
+    index: usize,
+}
+
+impl LineRule {
+    /// The positions form a path, in the order they were drawn.
+    pub fn new(positions: Vec<Position>, kind: LineKind, index: usize) -> Self {
+        LineRule {
+            positions,
+            kind,
+            regions: vec![],
+            index,
+        }
+    }
+
+    pub fn get_kind(&self) -> LineKind {
+        self.kind
+    }
+
+    /// Remembers the region of every position, which region sum lines are cut by.
+    pub fn set_regions(&mut self, regions: &[Vec<usize>]) {
+        self.regions = self
+            .positions
+            .iter()
+            .map(|pos| regions[pos.row() - 1][pos.col() - 1])
+            .collect();
+    }
+
+    /// Runs of consecutive positions within one region.
+    pub fn segments(&self) -> Vec<Vec<Position>> {
+        let mut segments: Vec<Vec<Position>> = vec![];
+        for (i, &pos) in self.positions.iter().enumerate() {
+            let same = i > 0 && self.regions.get(i) == self.regions.get(i - 1);
+            match segments.last_mut() {
+                Some(segment) if same => segment.push(pos),
+                _ => segments.push(vec![pos]),
+            }
+        }
+        segments
+    }
+
+    pub fn check(&self, board: &Board) -> RuleCheckResult {
+        let side = board.get_digits();
+        let values: Vec<usize> = self
+            .positions
+            .iter()
+            .map(|&pos| board.get_value(pos))
+            .collect();
+        let filled: Vec<usize> = values.iter().copied().filter(|&v| v > 0).collect();
+
+        let broken = match self.kind {
+            LineKind::Palindrome => values
+                .iter()
+                .zip(values.iter().rev())
+                .any(|(&a, &b)| a > 0 && b > 0 && a != b),
+            LineKind::Renban => {
+                let mut distinct = filled.clone();
+                distinct.sort();
+                distinct.dedup();
+                let spread = match (distinct.first(), distinct.last()) {
+                    (Some(low), Some(high)) => high - low,
+                    _ => 0,
+                };
+                distinct.len() != filled.len() || spread >= self.positions.len()
+            }
+            LineKind::Whispers => values.windows(2).any(|pair| {
+                pair[0] > 0
+                    && pair[1] > 0
+                    && pair[0].abs_diff(pair[1]) < LineKind::whisper_gap(side)
+            }),
+            LineKind::RegionSum => {
+                let sums: Vec<(usize, bool)> = self
+                    .segments()
+                    .iter()
+                    .map(|segment| {
+                        let values: Vec<usize> =
+                            segment.iter().map(|&pos| board.get_value(pos)).collect();
+                        (values.iter().sum(), !values.contains(&0))
+                    })
+                    .collect();
+                let complete = sums.iter().filter(|s| s.1).map(|s| s.0);
+                match (complete.clone().min(), complete.max()) {
+                    (Some(low), Some(high)) => {
+                        low != high || sums.iter().any(|&(sum, _)| sum > low)
+                    }
+                    _ => false,
+                }
+            }
+        };
+
+        if broken {
+            RuleCheckResult::Critical(format!(
+                "({}): positions {:?} break the line",
+                self.kind, self.positions
+            ))
+        } else if filled.len() < self.positions.len() {
+            RuleCheckResult::Unfulfilled(format!(
+                "({}): positions {:?} are not filled",
+                self.kind, self.positions
+            ))
+        } else {
+            RuleCheckResult::Ok
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct QuadRule {
+    pub positions: Vec<Position>,
+    corner: Corner,
+    digits: Vec<usize>,
+    index: usize,
+}
+
+impl QuadRule {
+    /// Every listed digit appears among the four cells around `corner`, a repeated one as often as listed.
+    pub fn new(corner: Corner, digits: Vec<usize>, index: usize) -> Self {
+        QuadRule {
+            positions: corner.cells().to_vec(),
+            corner,
+            digits,
+            index,
+        }
+    }
+
+    pub fn get_corner(&self) -> Corner {
+        self.corner
+    }
+
+    pub fn get_digits(&self) -> &[usize] {
+        &self.digits
+    }
+
+    /// Listed digits not yet matched by a filled cell.
+    pub fn missing(&self, values: &[usize]) -> Vec<usize> {
+        let mut missing = self.digits.clone();
+        for value in values {
+            if let Some(i) = missing.iter().position(|d| d == value) {
+                missing.swap_remove(i);
+            }
+        }
+        missing
+    }
+
+    pub fn check(&self, board: &Board) -> RuleCheckResult {
+        let values: Vec<usize> = self
+            .positions
+            .iter()
+            .map(|&pos| board.get_value(pos))
+            .collect();
+        let empty = values.iter().filter(|&&v| v == 0).count();
+
+        if self.missing(&values).len() > empty {
+            RuleCheckResult::Critical(format!(
+                "(quad): positions {:?} should contain {:?}",
+                self.positions, self.digits
+            ))
+        } else if empty > 0 {
+            RuleCheckResult::Unfulfilled(format!(
+                "(quad): positions {:?} are not filled",
+                self.positions
+            ))
+        } else {
+            RuleCheckResult::Ok
+        }
+    }
+}
+
+/// A constraint written as text, see `logic::expression`. Only the text is saved.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(try_from = "ExpressionSource", into = "ExpressionSource")]
+pub struct ExpressionRule {
+    pub positions: Vec<Position>,
+    source: String,
+    condition: Condition,
+    index: usize,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ExpressionSource {
+    source: String,
+    index: usize,
+}
+
+impl ExpressionRule {
+    pub fn new(source: &str, index: usize) -> Result<Self, String> {
+        let condition: Condition = source.parse()?;
+        Ok(ExpressionRule {
+            positions: condition.cells(),
+            source: source.trim().to_string(),
+            condition,
+            index,
+        })
+    }
+
+    pub fn get_source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn get_condition(&self) -> &Condition {
+        &self.condition
+    }
+
+    pub fn check(&self, board: &Board) -> RuleCheckResult {
+        let side = board.get_digits() as isize;
+        let bounds = |pos: Position| match board.get_value(pos) as isize {
+            0 => (1, side),
+            value => (value, value),
+        };
+
+        if !self.condition.possible(&bounds) {
+            RuleCheckResult::Critical(format!(
+                "({}): positions {:?} break the constraint",
+                self.source, self.positions
+            ))
+        } else if self.positions.iter().any(|&pos| board.get_value(pos) == 0) {
+            RuleCheckResult::Unfulfilled(format!(
+                "({}): positions {:?} are not filled",
+                self.source, self.positions
+            ))
+        } else {
+            RuleCheckResult::Ok
+        }
+    }
+}
+
+impl TryFrom<ExpressionSource> for ExpressionRule {
+    type Error = String;
+
+    fn try_from(saved: ExpressionSource) -> Result<Self, Self::Error> {
+        ExpressionRule::new(&saved.source, saved.index)
+    }
+}
+
+impl From<ExpressionRule> for ExpressionSource {
+    fn from(rule: ExpressionRule) -> Self {
+        ExpressionSource {
+            source: rule.source,
+            index: rule.index,
+        }
+    }
+}
+
+/// Constraints that apply to every matching pair of cells instead of to selected positions.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GlobalRule {
+    KropkiNegative,
+    XVNegative,
+    AntiKnight,
+    AntiKing,
+    NonConsecutive,
+}
+
+impl GlobalRule {
+    pub fn pairs(&self, board: &Board, rules: &[Rule]) -> Vec<(Position, Position)> {
+        match self {
+            GlobalRule::KropkiNegative | GlobalRule::XVNegative => {
+                let marked: Vec<&Vec<Position>> = rules
+                    .iter()
+                    .filter_map(|rule| match (self, rule) {
+                        (GlobalRule::KropkiNegative, Rule::Kropki(r)) => Some(&r.positions),
+                        (GlobalRule::XVNegative, Rule::XV(r)) => Some(&r.positions),
+                        _ => None,
+                    })
+                    .collect();
+                offset_pairs(board, &[(0, 1), (1, 0)])
+                    .into_iter()
+                    .filter(|&(a, b)| {
+                        !marked
+                            .iter()
+                            .any(|mark| mark.contains(&a) && mark.contains(&b))
+                    })
+                    .collect()
+            }
+            GlobalRule::AntiKnight => offset_pairs(board, &[(1, -2), (1, 2), (2, -1), (2, 1)]),
+            GlobalRule::AntiKing => offset_pairs(board, &[(0, 1), (1, -1), (1, 0), (1, 1)]),
+            GlobalRule::NonConsecutive => offset_pairs(board, &[(0, 1), (1, 0)]),
+        }
+    }
+
+    /// Whether the rule only forbids equal digits, so its pairs act as extra peers.
+    pub fn is_distinct(&self) -> bool {
+        matches!(self, GlobalRule::AntiKnight | GlobalRule::AntiKing)
+    }
+
+    /// Whether two digits may stand on a pair of cells returned by `pairs`.
+    pub fn allows(&self, a: usize, b: usize) -> bool {
+        match self {
+            GlobalRule::KropkiNegative => {
+                !KropkiColor::White.holds(a, b) && !KropkiColor::Black.holds(a, b)
+            }
+            GlobalRule::XVNegative => !XVMark::X.holds(a, b) && !XVMark::V.holds(a, b),
+            GlobalRule::AntiKnight | GlobalRule::AntiKing => a != b,
+            GlobalRule::NonConsecutive => a.abs_diff(b) != 1,
+        }
+    }
+
+    pub fn check_pair(&self, board: &Board, a: Position, b: Position) -> RuleCheckResult {
+        let (value1, value2) = (board.get_value(a), board.get_value(b));
+        if value1 > 0 && value2 > 0 && !self.allows(value1, value2) {
+            RuleCheckResult::Critical(format!(
+                "({}): positions {:?} and {:?} break the rule",
+                self, a, b
+            ))
+        } else {
+            RuleCheckResult::Ok
+        }
+    }
+
+    pub fn check(&self, board: &Board, rules: &[Rule]) -> RuleCheckResult {
+        self.pairs(board, rules)
+            .into_iter()
+            .map(|(a, b)| self.check_pair(board, a, b))
+            .find(|result| matches!(result, RuleCheckResult::Critical(_)))
+            .unwrap_or(RuleCheckResult::Ok)
+    }
+}
+
+impl Display for GlobalRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GlobalRule::KropkiNegative => write!(f, "kropki negative"),
+            GlobalRule::XVNegative => write!(f, "xv negative"),
+            GlobalRule::AntiKnight => write!(f, "anti-knight"),
+            GlobalRule::AntiKing => write!(f, "anti-king"),
+            GlobalRule::NonConsecutive => write!(f, "non-consecutive"),
+        }
+    }
+}
+
+fn offset_pairs(board: &Board, offsets: &[(isize, isize)]) -> Vec<(Position, Position)> {
+    let side = board.get_side();
+    let mut pairs = vec![];
+    for pos in board.cells() {
+        for &(rows, cols) in offsets {
+            if let Some(other) = pos.offset(rows, cols, side) {
+                if board.contains(other) {
+                    pairs.push((pos, other));
+                }
+            }
+        }
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(row: usize, col: usize) -> Position {
+        Position::new(row, col, 9).unwrap()
+    }
+
+    fn board(values: &[(usize, usize, usize)]) -> Board {
+        let mut board = Board::new(9);
+        for &(row, col, value) in values {
+            board.set_value(pos(row, col), value);
+        }
+        board
+    }
+
+    fn outcome(rule: Rule, values: &[(usize, usize, usize)]) -> &'static str {
+        match rule.check(&board(values)) {
+            RuleCheckResult::Critical(_) => "critical",
+            RuleCheckResult::Unfulfilled(_) => "unfulfilled",
+            RuleCheckResult::Ok => "ok",
+        }
+    }
+
+    #[test]
+    fn cage_rejects_repeats_and_wrong_sums() {
+        let cells = vec![pos(1, 1), pos(1, 2), pos(2, 1)];
+        let rule = || Rule::Cage(CageRule::new(cells.clone(), Some(10), 0));
+        assert_eq!(outcome(rule(), &[(1, 1, 3), (1, 2, 3)]), "critical");
+        assert_eq!(outcome(rule(), &[(1, 1, 8), (1, 2, 3)]), "critical");
+        assert_eq!(
+            outcome(rule(), &[(1, 1, 1), (1, 2, 2), (2, 1, 3)]),
+            "critical"
+        );
+        assert_eq!(outcome(rule(), &[(1, 1, 1), (1, 2, 2)]), "unfulfilled");
+        assert_eq!(outcome(rule(), &[(1, 1, 1), (1, 2, 2), (2, 1, 7)]), "ok");
+    }
+
+    #[test]
+    fn arrow_circle_reads_as_a_number() {
+        let cells = vec![pos(1, 1), pos(1, 2), pos(2, 1), pos(2, 2)];
+        let rule = || Rule::Arrow(ArrowRule::new(cells.clone(), 2, 0));
+        let circle = [(1, 1, 1), (1, 2, 2)];
+        assert_eq!(
+            outcome(rule(), &[circle[0], circle[1], (2, 1, 5)]),
+            "unfulfilled"
+        );
+        assert_eq!(
+            outcome(rule(), &[circle[0], circle[1], (2, 1, 5), (2, 2, 7)]),
+            "ok"
+        );
+        assert_eq!(
+            outcome(rule(), &[circle[0], circle[1], (2, 1, 5), (2, 2, 8)]),
+            "critical"
+        );
+    }
+
+    #[test]
+    fn multi_cell_circles_need_decimal_digits() {
+        let arrow = |circle| Rule::Arrow(ArrowRule::new(vec![], circle, 0));
+        assert!(arrow(2).check_shape(9).is_ok());
+        assert!(arrow(1).check_shape(16).is_ok());
+        assert!(arrow(2).check_shape(16).is_err());
+    }
+
+    #[test]
+    fn digit_filters_split_the_digits() {
+        let allowed = |filter: DigitFilter| -> Vec<usize> {
+            (1..=9).filter(|&d| filter.allows(d, 9)).collect()
+        };
+        assert_eq!(allowed(DigitFilter::Even), [2, 4, 6, 8]);
+        assert_eq!(allowed(DigitFilter::Odd), [1, 3, 5, 7, 9]);
+        assert_eq!(allowed(DigitFilter::Low), [1, 2, 3, 4]);
+        assert_eq!(allowed(DigitFilter::High), [6, 7, 8, 9]);
+        assert_eq!(allowed(DigitFilter::Digits(vec![3, 7])), [3, 7]);
+    }
+
+    #[test]
+    fn restriction_rejects_filtered_digits() {
+        let rule = || {
+            Rule::Restriction(RestrictionRule::new(
+                vec![pos(1, 1), pos(1, 2)],
+                DigitFilter::Even,
+                0,
+            ))
+        };
+        assert_eq!(outcome(rule(), &[(1, 1, 3)]), "critical");
+        assert_eq!(outcome(rule(), &[(1, 1, 4)]), "unfulfilled");
+        assert_eq!(outcome(rule(), &[(1, 1, 4), (1, 2, 8)]), "ok");
+    }
+
+    #[test]
+    fn sum_compares_the_total() {
+        let rule = || Rule::Sum(SumRule::new(vec![pos(1, 1), pos(2, 2)], 10, 0));
+        assert_eq!(outcome(rule(), &[(1, 1, 4)]), "unfulfilled");
+        assert_eq!(outcome(rule(), &[(1, 1, 4), (2, 2, 6)]), "ok");
+        assert_eq!(outcome(rule(), &[(1, 1, 4), (2, 2, 7)]), "critical");
+    }
+
+    #[test]
+    fn relation_orders_two_cells() {
+        let rule = || {
+            let mut rule = RelationRule::new(0);
+            rule.positions = vec![pos(1, 1), pos(1, 2)];
+            Rule::Relation(rule)
+        };
+        assert_eq!(outcome(rule(), &[(1, 1, 5)]), "unfulfilled");
+        assert_eq!(outcome(rule(), &[(1, 1, 5), (1, 2, 6)]), "ok");
+        assert_eq!(outcome(rule(), &[(1, 1, 5), (1, 2, 3)]), "critical");
+    }
+
+    #[test]
+    fn thermometer_rises_from_the_bulb() {
+        let cells = vec![pos(1, 1), pos(1, 2), pos(1, 3)];
+        let rule = || Rule::Thermometer(ThermometerRule::new(cells.clone(), 0));
+        assert_eq!(outcome(rule(), &[(1, 1, 2), (1, 3, 4)]), "unfulfilled");
+        assert_eq!(outcome(rule(), &[(1, 1, 2), (1, 3, 3)]), "critical");
+        assert_eq!(outcome(rule(), &[(1, 1, 2), (1, 2, 3), (1, 3, 9)]), "ok");
+        assert_eq!(
+            outcome(rule(), &[(1, 1, 2), (1, 2, 2), (1, 3, 9)]),
+            "critical"
+        );
+    }
+
+    fn kropki(color: KropkiColor, cells: Vec<Position>) -> Rule {
+        let mut rule = KropkiRule::new(color, 0);
+        rule.positions = cells;
+        Rule::Kropki(rule)
+    }
+
+    #[test]
+    fn kropki_dots_compare_neighbours() {
+        let white = || kropki(KropkiColor::White, vec![pos(1, 1), pos(1, 2)]);
+        let black = || kropki(KropkiColor::Black, vec![pos(1, 1), pos(1, 2)]);
+        assert_eq!(outcome(white(), &[(1, 1, 4)]), "unfulfilled");
+        assert_eq!(outcome(white(), &[(1, 1, 4), (1, 2, 5)]), "ok");
+        assert_eq!(outcome(white(), &[(1, 1, 4), (1, 2, 8)]), "critical");
+        assert_eq!(outcome(black(), &[(1, 1, 4), (1, 2, 8)]), "ok");
+        assert_eq!(outcome(black(), &[(1, 1, 4), (1, 2, 5)]), "critical");
+
+        let negative = GlobalRule::KropkiNegative;
+        let rules = [white()];
+        let board = board(&[(1, 1, 4), (1, 2, 5), (2, 1, 5)]);
+        assert!(matches!(
+            negative.check(&board, &rules),
+            RuleCheckResult::Critical(_)
+        ));
+        assert!(matches!(
+            negative.check(&board, &[]),
+            RuleCheckResult::Critical(_)
+        ));
+        let board = self::board(&[(1, 1, 4), (1, 2, 5)]);
+        assert!(matches!(
+            negative.check(&board, &rules),
+            RuleCheckResult::Ok
+        ));
+    }
+
+    #[test]
+    fn kropki_dots_join_two_adjacent_cells() {
+        let shape = |cells| kropki(KropkiColor::White, cells).check_shape(9);
+        assert!(shape(vec![pos(1, 1), pos(2, 1)]).is_ok());
+        assert!(shape(vec![pos(1, 1), pos(2, 2)]).is_err());
+        assert!(shape(vec![pos(1, 1), pos(1, 2), pos(1, 3)]).is_err());
+    }
+
+    fn xv(mark: XVMark, cells: Vec<Position>) -> Rule {
+        let mut rule = XVRule::new(mark, 0);
+        rule.positions = cells;
+        Rule::XV(rule)
+    }
+
+    #[test]
+    fn xv_marks_sum_neighbours() {
+        let x = || xv(XVMark::X, vec![pos(1, 1), pos(2, 1)]);
+        assert_eq!(outcome(x(), &[(1, 1, 3)]), "unfulfilled");
+        assert_eq!(outcome(x(), &[(1, 1, 3), (2, 1, 7)]), "ok");
+        assert_eq!(outcome(x(), &[(1, 1, 3), (2, 1, 2)]), "critical");
+        let v = xv(XVMark::V, vec![pos(1, 1), pos(2, 1)]);
+        assert_eq!(outcome(v, &[(1, 1, 3), (2, 1, 2)]), "ok");
+
+        let negative = GlobalRule::XVNegative;
+        let board = board(&[(1, 1, 3), (2, 1, 7)]);
+        assert!(matches!(
+            negative.check(&board, &[x()]),
+            RuleCheckResult::Ok
+        ));
+        assert!(matches!(
+            negative.check(&board, &[]),
+            RuleCheckResult::Critical(_)
+        ));
+    }
+
+    #[test]
+    fn xv_marks_join_two_adjacent_cells() {
+        assert!(xv(XVMark::V, vec![pos(1, 1), pos(1, 2)])
+            .check_shape(9)
+            .is_ok());
+        assert!(xv(XVMark::V, vec![pos(1, 1), pos(1, 3)])
+            .check_shape(9)
+            .is_err());
+    }
+
+    #[test]
+    fn chess_rules_forbid_repeats_a_move_apart() {
+        let critical = |rule: GlobalRule, values| {
+            matches!(
+                rule.check(&board(values), &[]),
+                RuleCheckResult::Critical(_)
+            )
+        };
+        assert!(critical(GlobalRule::AntiKnight, &[(5, 5, 5), (3, 4, 5)]));
+        assert!(!critical(GlobalRule::AntiKnight, &[(5, 5, 5), (3, 3, 5)]));
+        assert!(!critical(GlobalRule::AntiKnight, &[(5, 5, 5), (3, 4, 6)]));
+        assert!(critical(GlobalRule::AntiKing, &[(3, 3, 5), (4, 4, 5)]));
+        assert!(!critical(GlobalRule::AntiKing, &[(3, 3, 5), (5, 5, 5)]));
+    }
+
+    #[test]
+    fn little_killer_follows_the_diagonal() {
+        let clue = Outside::new(0, 7, 9).unwrap();
+        let rule = LittleKillerRule::new(clue, Diagonal::DownRight, 10, 9, 0);
+        assert_eq!(rule.positions, [pos(1, 8), pos(2, 9)]);
+        let corner = Outside::new(10, 0, 9).unwrap();
+        assert_eq!(
+            LittleKillerRule::new(corner, Diagonal::UpRight, 45, 9, 0)
+                .positions
+                .len(),
+            9
+        );
+        assert!(LittleKillerRule::new(clue, Diagonal::UpLeft, 10, 9, 0)
+            .positions
+            .is_empty());
+
+        let rule =
+            || Rule::LittleKiller(LittleKillerRule::new(clue, Diagonal::DownRight, 10, 9, 0));
+        assert_eq!(outcome(rule(), &[(1, 8, 4)]), "unfulfilled");
+        assert_eq!(outcome(rule(), &[(1, 8, 4), (2, 9, 6)]), "ok");
+        assert_eq!(outcome(rule(), &[(1, 8, 4), (2, 9, 7)]), "critical");
+    }
+
+    #[test]
+    fn sandwich_sums_between_the_smallest_and_largest_digit() {
+        let clue = Outside::new(1, 0, 9).unwrap();
+        let rule = || Rule::Sandwich(SandwichRule::new(clue, 5, 9, 0));
+        assert_eq!(outcome(rule(), &[(1, 2, 1), (1, 5, 2)]), "unfulfilled");
+        assert_eq!(
+            outcome(rule(), &[(1, 2, 1), (1, 3, 2), (1, 5, 9)]),
+            "unfulfilled"
+        );
+        assert_eq!(
+            outcome(rule(), &[(1, 2, 9), (1, 3, 2), (1, 4, 3), (1, 5, 1)]),
+            "ok"
+        );
+        assert_eq!(
+            outcome(rule(), &[(1, 2, 1), (1, 3, 2), (1, 4, 4), (1, 5, 9)]),
+            "critical"
+        );
+    }
+
+    fn line(kind: LineKind, cells: &[(usize, usize)]) -> Rule {
+        let cells = cells.iter().map(|&(row, col)| pos(row, col)).collect();
+        let mut rule = LineRule::new(cells, kind, 0);
+        let boxes: Vec<Vec<usize>> = (0..9)
+            .map(|row| (0..9).map(|col| row / 3 * 3 + col / 3).collect())
+            .collect();
+        rule.set_regions(&boxes);
+        Rule::Line(rule)
+    }
+
+    #[test]
+    fn lines_check_by_kind() {
+        let path = [(1, 1), (1, 2), (1, 3)];
+        let palindrome = || line(LineKind::Palindrome, &path);
+        assert_eq!(
+            outcome(palindrome(), &[(1, 1, 4), (1, 3, 4)]),
+            "unfulfilled"
+        );
+        assert_eq!(
+            outcome(palindrome(), &[(1, 1, 4), (1, 2, 2), (1, 3, 4)]),
+            "ok"
+        );
+        assert_eq!(outcome(palindrome(), &[(1, 1, 4), (1, 3, 5)]), "critical");
+
+        let renban = || line(LineKind::Renban, &path);
+        assert_eq!(outcome(renban(), &[(1, 1, 4), (1, 2, 2), (1, 3, 3)]), "ok");
+        assert_eq!(outcome(renban(), &[(1, 1, 4), (1, 3, 1)]), "critical");
+        assert_eq!(outcome(renban(), &[(1, 1, 4), (1, 3, 4)]), "critical");
+
+        let whispers = || line(LineKind::Whispers, &path);
+        assert_eq!(
+            outcome(whispers(), &[(1, 1, 1), (1, 2, 6), (1, 3, 1)]),
+            "ok"
+        );
+        assert_eq!(outcome(whispers(), &[(1, 1, 1), (1, 2, 5)]), "critical");
+
+        // the line crosses from the first box into the second
+        let region_sum = || line(LineKind::RegionSum, &[(1, 2), (1, 3), (1, 4)]);
+        assert_eq!(
+            outcome(region_sum(), &[(1, 2, 2), (1, 3, 5), (1, 4, 7)]),
+            "ok"
+        );
+        assert_eq!(
+            outcome(region_sum(), &[(1, 2, 2), (1, 3, 5), (1, 4, 8)]),
+            "critical"
+        );
+        assert_eq!(outcome(region_sum(), &[(1, 2, 5), (1, 4, 3)]), "critical");
+        assert_eq!(
+            outcome(region_sum(), &[(1, 2, 2), (1, 4, 8)]),
+            "unfulfilled"
+        );
+    }
+
+    #[test]
+    fn lines_follow_neighbouring_cells() {
+        assert!(line(LineKind::Renban, &[(1, 1), (2, 2), (2, 3)])
+            .check_shape(9)
+            .is_ok());
+        assert!(line(LineKind::Renban, &[(1, 1), (1, 3)])
+            .check_shape(9)
+            .is_err());
+    }
+
+    #[test]
+    fn non_consecutive_checks_orthogonal_neighbours() {
+        let critical = |values| {
+            let board = board(values);
+            matches!(
+                GlobalRule::NonConsecutive.check(&board, &[]),
+                RuleCheckResult::Critical(_)
+            )
+        };
+        assert!(critical(&[(5, 5, 5), (5, 6, 6)]));
+        assert!(critical(&[(5, 5, 5), (4, 5, 4)]));
+        assert!(!critical(&[(5, 5, 5), (6, 6, 6)]));
+        assert!(!critical(&[(5, 5, 5), (5, 6, 7)]));
+    }
+
+    #[test]
+    fn quad_needs_its_digits_around_the_corner() {
+        let corner = Corner::new(1, 1, 9).unwrap();
+        let rule = || Rule::Quad(QuadRule::new(corner, vec![1, 2], 0));
+        assert_eq!(
+            rule().get_positions(),
+            [pos(1, 1), pos(1, 2), pos(2, 1), pos(2, 2)]
+        );
+        assert_eq!(outcome(rule(), &[(1, 1, 1), (2, 2, 5)]), "unfulfilled");
+        assert_eq!(
+            outcome(rule(), &[(1, 1, 5), (1, 2, 6), (2, 1, 7)]),
+            "critical"
+        );
+        assert_eq!(
+            outcome(rule(), &[(1, 1, 1), (1, 2, 6), (2, 1, 7), (2, 2, 2)]),
+            "ok"
+        );
+    }
+
+    #[test]
+    fn expression_checks_its_condition() {
+        let rule = || Rule::Expression(ExpressionRule::new("r1c1 + r1c2 == 2 * r2c2", 0).unwrap());
+        assert_eq!(outcome(rule(), &[(1, 1, 1), (1, 2, 3)]), "unfulfilled");
+        assert_eq!(outcome(rule(), &[(1, 1, 1), (1, 2, 3), (2, 2, 2)]), "ok");
+        assert_eq!(
+            outcome(rule(), &[(1, 1, 1), (1, 2, 3), (2, 2, 3)]),
+            "critical"
+        );
+        // r1c1 + 1 can never reach 18
+        assert_eq!(outcome(rule(), &[(1, 2, 1), (2, 2, 9)]), "critical");
+
+        assert!(ExpressionRule::new("r1c1 +", 0).is_err());
+        let saved = serde_json::to_string(&rule()).unwrap();
+        assert!(saved.contains("r1c1 + r1c2 == 2 * r2c2"));
+        let loaded: Rule = serde_json::from_str(&saved).unwrap();
+        assert_eq!(loaded.get_positions(), [pos(1, 1), pos(1, 2), pos(2, 2)]);
+    }
+}
@@ -1,159 +1,688 @@
 use super::board::{Board, Position};
+use super::candidates::{Candidates, Domains};
+use super::constraint::CustomRule;
+use super::deduction::{LogicalSolver, Technique};
+use super::digits::DigitSet;
+use super::expression::Condition;
 use super::game::Game;
-use super::rules::{Rule, RuleCheckResult};
+use super::rules::{GlobalRule, LineKind, QuadRule, Rule, RuleCheckResult};
 use crate::for_pos;
 use rand::seq::SliceRandom;
 
+type Propagator = fn(&mut Solver, &[bool]) -> Option<()>;
+
 pub struct Solver {
-    board: Board,
+    state: Candidates,
     solution: Option<Board>,
     rules: Vec<Rule>,
+    global_rules: Vec<GlobalRule>,
     n: usize,
-    options: Vec<Vec<Vec<usize>>>,
-    position_rules: Vec<Vec<Vec<usize>>>,
+    digits: usize,
+    peers: Vec<Vec<Position>>,
+    houses: Vec<Vec<Position>>,
+    sums: Vec<(Vec<(Position, isize)>, isize)>,
+    relations: Vec<(Position, Position)>,
+    pairs: Vec<(Position, Position, Vec<DigitSet>)>,
+    sandwiches: Vec<(Vec<Position>, usize)>,
+    renbans: Vec<Vec<Position>>,
+    region_sums: Vec<Vec<Vec<Position>>>,
+    quads: Vec<QuadRule>,
+    expressions: Vec<(Condition, Vec<Position>)>,
+    customs: Vec<CustomRule>,
+    marks: Vec<usize>,
     is_valid: bool,
     rng: rand::rngs::ThreadRng,
     use_randomization: bool,
+    attempts: usize,
 }
 
 impl Solver {
     pub fn new(game: Game, use_randomization: bool) -> Self {
-        let n = game.get_side();
+        let board = game.board();
+        let (n, digits) = (board.get_side(), board.get_digits());
 
-        let mut options: Vec<Vec<Vec<usize>>> = vec![vec![(1..=n).collect(); n]; n];
-        let mut position_rules: Vec<Vec<Vec<usize>>> = vec![vec![vec![]; n]; n];
+        let mut peers: Vec<Vec<Position>> = vec![vec![]; n * n];
 
         let rules: Vec<Rule> = game
             .rules()
             .into_iter()
             .filter(|rule| match rule {
-                Rule::Permutation(_r) => rule.get_positions().len() == n,
+                Rule::Permutation(_r) => rule.get_positions().len() == digits,
                 Rule::Relation(_r) => rule.get_positions().len() == 2,
                 _ => true,
             })
             .collect();
 
         rules.iter().for_each(|rule| {
-            if let Rule::Permutation(_r) = rule {
-                for pos in rule.get_positions() {
-                    let row = pos.row() - 1;
-                    let col = pos.col() - 1;
-                    position_rules[row][col].push(rule.get_index());
+            let distinct = match rule {
+                Rule::Permutation(_) | Rule::Cage(_) => true,
+                Rule::Line(r) => r.get_kind() == LineKind::Renban,
+                Rule::Expression(r) => matches!(r.get_condition(), Condition::Distinct(_)),
+                _ => false,
+            };
+            if distinct {
+                let positions = rule.get_positions();
+                for pos in &positions {
+                    let cell_peers = &mut peers[pos.index(n)];
+                    for &other in &positions {
+                        if other != *pos && !cell_peers.contains(&other) {
+                            cell_peers.push(other);
+                        }
+                    }
+                }
+            }
+        });
+
+        let houses = rules
+            .iter()
+            .filter_map(|rule| match rule {
+                Rule::Permutation(r) => Some(r.positions.clone()),
+                _ => None,
+            })
+            .collect();
+
+        let sums = rules.iter().filter_map(linear_constraint).collect();
+
+        let relations = rules
+            .iter()
+            .flat_map(|rule| match rule {
+                Rule::Relation(r) => vec![(r.positions[0], r.positions[1])],
+                Rule::Thermometer(r) => r.positions.windows(2).map(|w| (w[0], w[1])).collect(),
+                _ => vec![],
+            })
+            .collect();
+
+        let mut pairs = vec![];
+        for rule in &rules {
+            let table = match rule {
+                Rule::Kropki(r) => pair_table(digits, |a, b| r.get_color().holds(a, b)),
+                Rule::XV(r) => pair_table(digits, |a, b| r.get_mark().holds(a, b)),
+                Rule::Line(r) => {
+                    let p = &r.positions;
+                    match r.get_kind() {
+                        LineKind::Palindrome => {
+                            let table = pair_table(digits, |a, b| a == b);
+                            for i in 0..p.len() / 2 {
+                                pairs.push((p[i], p[p.len() - 1 - i], table.clone()));
+                            }
+                        }
+                        LineKind::Whispers => {
+                            let gap = LineKind::whisper_gap(digits);
+                            let table = pair_table(digits, |a, b| a.abs_diff(b) >= gap);
+                            for w in p.windows(2) {
+                                pairs.push((w[0], w[1], table.clone()));
+                            }
+                        }
+                        LineKind::Renban | LineKind::RegionSum => {}
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            let positions = rule.get_positions();
+            if positions.len() == 2 {
+                pairs.push((positions[0], positions[1], table));
+            }
+        }
+
+        let renbans = rules
+            .iter()
+            .filter_map(|rule| match rule {
+                Rule::Line(r) if r.get_kind() == LineKind::Renban && r.positions.len() >= 2 => {
+                    Some(r.positions.clone())
+                }
+                _ => None,
+            })
+            .collect();
+
+        let region_sums = rules
+            .iter()
+            .filter_map(|rule| match rule {
+                Rule::Line(r) if r.get_kind() == LineKind::RegionSum => {
+                    Some(r.segments()).filter(|segments| segments.len() >= 2)
                 }
-            } else if let Rule::Relation(_r) = rule {
-                let pos1 = rule.get_positions()[0];
-                let pos2 = rule.get_positions()[1];
-                if options[pos1.row() - 1][pos1.col() - 1].last().unwrap_or(&0) == &(n - 1) {
-                    options[pos1.row() - 1][pos1.col() - 1].pop();
+                _ => None,
+            })
+            .collect();
+
+        let sandwiches = rules
+            .iter()
+            .filter_map(|rule| match rule {
+                Rule::Sandwich(r) if r.positions.len() >= 2 => {
+                    Some((r.positions.clone(), r.get_sum()))
                 }
-                if options[pos2.row() - 1][pos2.col() - 1]
-                    .first()
-                    .unwrap_or(&0)
-                    == &0
-                {
-                    options[pos2.row() - 1][pos2.col() - 1].remove(0);
+                _ => None,
+            })
+            .collect();
+
+        let quads = rules
+            .iter()
+            .filter_map(|rule| match rule {
+                Rule::Quad(r) => Some(r.clone()),
+                _ => None,
+            })
+            .collect();
+
+        let expressions = rules
+            .iter()
+            .filter_map(|rule| match rule {
+                Rule::Expression(r) => match r.get_condition() {
+                    Condition::Compare(..) => {
+                        Some((r.get_condition().clone(), r.get_condition().cells()))
+                    }
+                    Condition::Distinct(_) => None,
+                },
+                _ => None,
+            })
+            .collect();
+
+        let customs = rules
+            .iter()
+            .filter_map(|rule| match rule {
+                Rule::Custom(r) => Some(r.clone()),
+                _ => None,
+            })
+            .collect();
+
+        let global_rules = game.global_rules();
+        for global in &global_rules {
+            if global.is_distinct() {
+                for (a, b) in global.pairs(&board, &rules) {
+                    for (pos, other) in [(a, b), (b, a)] {
+                        let cell_peers = &mut peers[pos.index(n)];
+                        if !cell_peers.contains(&other) {
+                            cell_peers.push(other);
+                        }
+                    }
                 }
+                continue;
             }
-        });
+
+            let table = pair_table(digits, |a, b| global.allows(a, b));
+            for (a, b) in global.pairs(&board, &rules) {
+                pairs.push((a, b, table.clone()));
+            }
+        }
 
         let mut ret = Solver {
-            board: game.board(),
+            state: Candidates::new(&board),
             solution: None,
             rules,
+            global_rules,
             n,
-            options,
-            position_rules,
+            digits,
+            peers,
+            houses,
+            sums,
+            relations,
+            pairs,
+            sandwiches,
+            renbans,
+            region_sums,
+            quads,
+            expressions,
+            customs,
+            marks: vec![],
             is_valid: true,
             rng: rand::thread_rng(),
             use_randomization,
+            attempts: 0,
         };
 
-        let board = game.board();
+        for rule in &ret.rules {
+            if let Rule::Restriction(r) = rule {
+                let allowed = (1..=digits)
+                    .filter(|&digit| r.get_filter().allows(digit, digits))
+                    .fold(DigitSet::empty(), |set, digit| set.with(digit));
+                for &pos in &r.positions {
+                    ret.is_valid &= ret.state.restrict(pos, allowed).is_some();
+                }
+            }
+        }
 
-        for_pos!(n, |pos| {
-            let v = board.get_value(pos);
-            if board.get_value(pos) > 0 {
-                let ok = ret.place(pos, v);
-                ret.is_valid &= ok;
+        for pos in board.cells() {
+            let value = board.get_value(pos);
+            // an earlier clue may already have forced this one through propagation
+            if value > 0 && ret.state.value(pos) != value {
+                let placed = ret.place(pos, value);
+                ret.is_valid &= placed;
             }
-        });
+        }
+
+        ret.is_valid &= ret.propagate(vec![true; n * n]);
 
         ret
     }
 
     fn place(&mut self, pos: Position, digit: usize) -> bool {
-        let (row, col) = pos.coords();
+        if self.state.value(pos) != 0 || !self.state.options(pos).contains(digit) {
+            return false;
+        }
 
-        let original_value = self.board.get_value(pos);
-        let original_options = self.options.clone();
+        let mark = self.state.mark();
+        self.state.assign(pos, digit);
 
-        if !self.options[row][col].contains(&digit) {
-            return false;
+        if self.eliminate_peers(pos, digit) && self.propagate(self.state.changed_since(mark)) {
+            self.marks.push(mark);
+            true
+        } else {
+            self.state.undo_to(mark);
+            false
         }
+    }
 
-        self.board.set_value(pos, digit);
+    fn eliminate_peers(&mut self, pos: Position, digit: usize) -> bool {
+        self.peers[pos.index(self.n)]
+            .iter()
+            .all(|&peer| self.state.remove(peer, digit).is_some())
+    }
 
-        for &rule_index in &self.position_rules[row][col] {
-            let rule = &self.rules[rule_index];
-            if let RuleCheckResult::Critical(_) = rule.check(&self.board) {
-                self.board.set_value(pos, original_value);
-                self.options = original_options;
-                return false;
+    /// Runs the propagators until nothing changes. Every pass only looks at constraints with
+    /// a cell in `dirty`, which holds the cells changed since the previous pass.
+    fn propagate(&mut self, mut dirty: Vec<bool>) -> bool {
+        let steps: [Propagator; 10] = [
+            Self::propagate_singles,
+            Self::propagate_sums,
+            Self::propagate_relations,
+            Self::propagate_pairs,
+            Self::propagate_sandwiches,
+            Self::propagate_renbans,
+            Self::propagate_region_sums,
+            Self::propagate_quads,
+            Self::propagate_expressions,
+            Self::propagate_customs,
+        ];
+
+        while dirty.contains(&true) {
+            let mark = self.state.mark();
+            for step in steps {
+                if step(self, &dirty).is_none() {
+                    return false;
+                }
             }
+            dirty = self.state.changed_since(mark);
         }
+        true
+    }
 
-        for &rule_index in &self.position_rules[row][col] {
-            let rule = &self.rules[rule_index];
-            if let Rule::Permutation(_) = rule {
-                for pos in rule.get_positions() {
-                    let (r, c) = pos.coords();
+    /// Places cells with a single option left and digits with a single cell left in a house.
+    fn propagate_singles(&mut self, dirty: &[bool]) -> Option<()> {
+        let mut singles = vec![];
+        for cell in (0..dirty.len()).filter(|&cell| dirty[cell]) {
+            let pos = Position::from_index(cell, self.n).unwrap();
+            let options = self.state.options(pos);
+            if self.state.value(pos) == 0 && options.len() == 1 {
+                singles.push((pos, options.min()?));
+            }
+        }
+        for house in self
+            .houses
+            .iter()
+            .filter(|house| touches(dirty, self.n, house))
+        {
+            for digit in 1..=self.digits {
+                let mut hosts = house
+                    .iter()
+                    .filter(|&&pos| self.state.domain(pos).contains(digit));
+                match (hosts.next(), hosts.next()) {
+                    (None, _) => return None,
+                    (Some(&pos), None) if self.state.value(pos) == 0 => singles.push((pos, digit)),
+                    _ => {}
+                }
+            }
+        }
 
-                    if self.board.get_value(pos) == 0 {
-                        self.options[r][c].retain(|&x| x != digit);
-                        if self.options[r][c].is_empty() {
-                            self.board.set_value(pos, original_value);
-                            self.options = original_options;
-                            return false;
-                        }
+        for (pos, digit) in singles {
+            match self.state.value(pos) {
+                0 if self.state.options(pos).contains(digit) => {
+                    self.state.assign(pos, digit);
+                    if !self.eliminate_peers(pos, digit) {
+                        return None;
                     }
                 }
+                value if value == digit => {}
+                _ => return None,
             }
         }
 
-        true
+        Some(())
     }
 
-    fn unplace(&mut self, pos: Position) {
-        let (row, col) = pos.coords();
+    fn propagate_sums(&mut self, dirty: &[bool]) -> Option<()> {
+        for (terms, total) in &self.sums {
+            if !terms.iter().any(|&(pos, _)| dirty[pos.index(self.n)]) {
+                continue;
+            }
+            let ranges: Vec<(isize, isize)> = terms
+                .iter()
+                .map(|&(pos, weight)| {
+                    let (min, max) = self.state.bounds(pos);
+                    let (min, max) = (weight * min as isize, weight * max as isize);
+                    (min.min(max), min.max(max))
+                })
+                .collect();
+            let min_total: isize = ranges.iter().map(|r| r.0).sum();
+            let max_total: isize = ranges.iter().map(|r| r.1).sum();
 
-        let original_value = self.board.get_value(pos);
-        if original_value == 0 {
-            return;
+            if min_total > *total || max_total < *total {
+                return None;
+            }
+
+            for (&(pos, weight), &(min, max)) in terms.iter().zip(&ranges) {
+                let low = total - (max_total - max);
+                let high = total - (min_total - min);
+                let (low, high) = if weight > 0 {
+                    (low, high)
+                } else {
+                    (-high, -low)
+                };
+                let weight = weight.abs();
+
+                let first = -(-low).div_euclid(weight);
+                let last = high.div_euclid(weight);
+                let keep = DigitSet::range(first.max(0) as usize, last.max(0) as usize);
+                self.state.restrict(pos, keep)?;
+            }
+        }
+
+        Some(())
+    }
+
+    fn propagate_relations(&mut self, dirty: &[bool]) -> Option<()> {
+        for &(smaller, larger) in &self.relations {
+            if !touches(dirty, self.n, &[smaller, larger]) {
+                continue;
+            }
+            let (smaller_min, _) = self.state.bounds(smaller);
+            let (_, larger_max) = self.state.bounds(larger);
+
+            if smaller_min >= larger_max {
+                return None;
+            }
+            self.state
+                .restrict(smaller, DigitSet::range(1, larger_max - 1))?;
+            self.state
+                .restrict(larger, DigitSet::range(smaller_min + 1, self.digits))?;
+        }
+
+        Some(())
+    }
+
+    fn propagate_pairs(&mut self, dirty: &[bool]) -> Option<()> {
+        for (a, b, table) in &self.pairs {
+            if !touches(dirty, self.n, &[*a, *b]) {
+                continue;
+            }
+            let supported = |domain: DigitSet| {
+                domain
+                    .iter()
+                    .fold(DigitSet::empty(), |set, d| set.union(table[d]))
+            };
+            self.state.restrict(*b, supported(self.state.domain(*a)))?;
+            self.state.restrict(*a, supported(self.state.domain(*b)))?;
+        }
+
+        Some(())
+    }
+
+    /// Tries every placement of the crusts (1 and the largest digit) on a sandwich line and keeps
+    /// only the digits that some placement with a reachable inner sum supports.
+    fn propagate_sandwiches(&mut self, dirty: &[bool]) -> Option<()> {
+        let (low, high) = (1, self.digits);
+        let crusts = DigitSet::single(low).with(high);
+        let fillings = DigitSet::full(self.digits).difference(crusts);
+
+        for (line, sum) in self
+            .sandwiches
+            .iter()
+            .filter(|(line, _)| touches(dirty, self.n, line))
+        {
+            let domains: Vec<DigitSet> = line.iter().map(|&pos| self.state.domain(pos)).collect();
+            let mut supported = vec![DigitSet::empty(); line.len()];
+
+            for first in 0..line.len() {
+                for last in first + 1..line.len() {
+                    let ends = [(low, high), (high, low)]
+                        .into_iter()
+                        .filter(|&(a, b)| domains[first].contains(a) && domains[last].contains(b));
+                    let (first_digits, last_digits) = ends
+                        .fold((DigitSet::empty(), DigitSet::empty()), |(x, y), (a, b)| {
+                            (x.with(a), y.with(b))
+                        });
+                    if first_digits.is_empty() {
+                        continue;
+                    }
+
+                    let Some(bounds) = domains[first + 1..last]
+                        .iter()
+                        .map(|domain| {
+                            let domain = domain.intersection(fillings);
+                            Some((domain.min()?, domain.max()?))
+                        })
+                        .collect::<Option<Vec<(usize, usize)>>>()
+                    else {
+                        continue;
+                    };
+                    let min_total: usize = bounds.iter().map(|b| b.0).sum();
+                    let max_total: usize = bounds.iter().map(|b| b.1).sum();
+                    if min_total > *sum || max_total < *sum {
+                        continue;
+                    }
+
+                    supported[first] = supported[first].union(first_digits);
+                    supported[last] = supported[last].union(last_digits);
+                    for (k, &(min, max)) in (first + 1..last).zip(&bounds) {
+                        let keep = DigitSet::range(
+                            (sum + max).saturating_sub(max_total),
+                            sum + min - min_total,
+                        );
+                        supported[k] = supported[k].union(keep.intersection(fillings));
+                    }
+                    for k in (0..first).chain(last + 1..line.len()) {
+                        supported[k] = supported[k].union(fillings);
+                    }
+                }
+            }
+
+            for (&pos, &keep) in line.iter().zip(&supported) {
+                self.state.restrict(pos, keep)?;
+            }
+        }
+
+        Some(())
+    }
+
+    /// A renban line holds a run of consecutive digits, so every cell keeps only
+    /// the digits of runs that each cell on the line can still take part in.
+    fn propagate_renbans(&mut self, dirty: &[bool]) -> Option<()> {
+        for line in self
+            .renbans
+            .iter()
+            .filter(|line| touches(dirty, self.n, line))
+        {
+            if line.len() > self.digits {
+                return None;
+            }
+            let domains: Vec<DigitSet> = line.iter().map(|&pos| self.state.domain(pos)).collect();
+
+            let mut keep = DigitSet::empty();
+            for start in 1..=self.digits + 1 - line.len() {
+                let run = DigitSet::range(start, start + line.len() - 1);
+                if domains.iter().all(|d| !d.intersection(run).is_empty()) {
+                    keep = keep.union(run);
+                }
+            }
+
+            for &pos in line {
+                self.state.restrict(pos, keep)?;
+            }
+        }
+
+        Some(())
+    }
+
+    /// Every segment of a region sum line shares one total, so its range is the
+    /// overlap of the segment ranges and each cell is bounded like in a sum.
+    fn propagate_region_sums(&mut self, dirty: &[bool]) -> Option<()> {
+        for segments in &self.region_sums {
+            if !segments
+                .iter()
+                .any(|segment| touches(dirty, self.n, segment))
+            {
+                continue;
+            }
+            let bounds: Vec<Vec<(usize, usize)>> = segments
+                .iter()
+                .map(|segment| segment.iter().map(|&pos| self.state.bounds(pos)).collect())
+                .collect();
+            let totals: Vec<(usize, usize)> = bounds
+                .iter()
+                .map(|cells| {
+                    (
+                        cells.iter().map(|b| b.0).sum(),
+                        cells.iter().map(|b| b.1).sum(),
+                    )
+                })
+                .collect();
+
+            let low = totals.iter().map(|t| t.0).max().unwrap_or(0);
+            let high = totals.iter().map(|t| t.1).min().unwrap_or(0);
+            if low > high {
+                return None;
+            }
+
+            for ((segment, cells), &(min_total, max_total)) in
+                segments.iter().zip(&bounds).zip(&totals)
+            {
+                for (&pos, &(min, max)) in segment.iter().zip(cells) {
+                    let keep = DigitSet::range(
+                        (low + max).saturating_sub(max_total),
+                        high + min - min_total,
+                    );
+                    self.state.restrict(pos, keep)?;
+                }
+            }
         }
 
-        self.board.set_value(pos, 0);
+        Some(())
+    }
+
+    /// The digits still missing from a quad need enough open cells that can hold them;
+    /// when the count is tight those cells cannot take anything else.
+    fn propagate_quads(&mut self, dirty: &[bool]) -> Option<()> {
+        for quad in self
+            .quads
+            .iter()
+            .filter(|quad| touches(dirty, self.n, &quad.positions))
+        {
+            let values: Vec<usize> = quad
+                .positions
+                .iter()
+                .map(|&pos| self.state.value(pos))
+                .collect();
+            let missing = quad.missing(&values);
+            let open: Vec<Position> = quad
+                .positions
+                .iter()
+                .copied()
+                .filter(|&pos| self.state.value(pos) == 0)
+                .collect();
+
+            if missing.len() > open.len() {
+                return None;
+            }
+            let missing_set = DigitSet::from_digits(&missing);
+            if missing.len() == open.len() {
+                for &pos in &open {
+                    self.state.restrict(pos, missing_set)?;
+                }
+            }
 
-        for &rule_index in &self.position_rules[row][col] {
-            let rule = &self.rules[rule_index];
-            if let Rule::Permutation(_) = rule {
-                for pos in rule.get_positions() {
-                    let (r, c) = pos.coords();
-                    if self.board.get_value(pos) == 0
-                        && !self.options[r][c].contains(&original_value)
-                    {
-                        self.options[r][c].push(original_value);
+            for digit in missing_set.iter() {
+                let needed = missing.iter().filter(|&&d| d == digit).count();
+                let hosts: Vec<Position> = open
+                    .iter()
+                    .copied()
+                    .filter(|&pos| self.state.options(pos).contains(digit))
+                    .collect();
+                if hosts.len() < needed {
+                    return None;
+                }
+                if hosts.len() == needed {
+                    for pos in hosts {
+                        self.state.restrict(pos, DigitSet::single(digit))?;
                     }
                 }
             }
         }
+
+        Some(())
+    }
+
+    /// Keeps a digit only if the expression can still hold with the cell fixed to it
+    /// and the other cells anywhere within their bounds.
+    fn propagate_expressions(&mut self, dirty: &[bool]) -> Option<()> {
+        for (condition, cells) in &self.expressions {
+            if !touches(dirty, self.n, cells) {
+                continue;
+            }
+            for &pos in cells {
+                let keep = self.state.domain(pos).iter().filter(|&digit| {
+                    condition.possible(&|cell: Position| {
+                        let (low, high) = match cell == pos {
+                            true => (digit, digit),
+                            false => self.state.bounds(cell),
+                        };
+                        (low as isize, high as isize)
+                    })
+                });
+                let keep = keep.fold(DigitSet::empty(), |set, digit| set.with(digit));
+                self.state.restrict(pos, keep)?;
+            }
+        }
+
+        Some(())
+    }
+
+    /// Lets rules from other crates remove candidates through `Constraint::propagate`,
+    /// and fails as soon as their `check` finds a broken rule among the filled cells.
+    /// They run on every pass, as they may look at cells beyond their own.
+    fn propagate_customs(&mut self, _dirty: &[bool]) -> Option<()> {
+        for rule in &self.customs {
+            rule.get().propagate(&mut Domains::new(&mut self.state))?;
+            if let RuleCheckResult::Critical(_) = rule.get().check(self.state.board()) {
+                return None;
+            }
+        }
+
+        Some(())
+    }
+
+    fn unplace(&mut self, pos: Position) {
+        if self.state.value(pos) == 0 {
+            return;
+        }
+
+        if let Some(mark) = self.marks.pop() {
+            self.state.undo_to(mark);
+        }
     }
 
     fn check_rules(&self) -> usize {
         let mut ret = (0, 0);
 
-        for rule in &self.rules {
-            let result = rule.check(&self.board);
+        let board = self.state.board();
+        let global_results = self
+            .global_rules
+            .iter()
+            .map(|rule| rule.check(board, &self.rules));
 
+        for result in self
+            .rules
+            .iter()
+            .map(|rule| rule.check(board))
+            .chain(global_results)
+        {
             match result {
                 RuleCheckResult::Critical(_) => ret.0 += 1,
                 RuleCheckResult::Unfulfilled(_) => ret.1 += 1,
@@ -180,17 +709,17 @@ impl Solver {
     }
 
     pub fn get_options(&self, pos: Position) -> Vec<usize> {
-        self.options[pos.row() - 1][pos.col() - 1].clone()
+        self.state.options(pos).to_vec()
     }
 
     fn get_next_position(&self) -> Option<Position> {
-        let mut ret: (Vec<usize>, Option<Position>) = (vec![], None);
+        let mut ret: (usize, Option<Position>) = (0, None);
 
         for_pos!(self.n, |pos| {
-            if self.board.get_value(pos) == 0 {
-                let options = &self.get_options(pos);
-                if (!options.is_empty()) && (ret.1.is_none() || options.len() < ret.0.len()) {
-                    ret = (options.clone(), Some(pos));
+            if self.state.value(pos) == 0 {
+                let count = self.state.options(pos).len();
+                if count > 0 && (ret.1.is_none() || count < ret.0) {
+                    ret = (count, Some(pos));
                 }
             }
         });
@@ -208,6 +737,7 @@ impl Solver {
                     opt.shuffle(&mut self.rng);
                 }
                 for value in opt {
+                    self.attempts += 1;
                     if self.place(pos, value) && self.solve_recursive() {
                         return true;
                     }
@@ -218,7 +748,7 @@ impl Solver {
             None => {
                 let state = self.check_rules();
                 if state == 2 {
-                    self.solution = Some(self.board.clone());
+                    self.solution = Some(self.state.board().clone());
                     true
                 } else {
                     false
@@ -227,30 +757,605 @@ impl Solver {
         }
     }
 
+    /// Returns the number of solutions, but stops searching once `limit` is reached.
+    pub fn count_solutions(&mut self, limit: usize) -> usize {
+        let mut nodes = None;
+        self.count(limit, &mut nodes).unwrap_or(0)
+    }
+
+    /// Like `count_solutions`, but gives up with `None` once `max_nodes` placements were tried
+    /// without settling the count.
+    pub fn count_solutions_within(&mut self, limit: usize, max_nodes: usize) -> Option<usize> {
+        let mut nodes = Some(max_nodes);
+        self.count(limit, &mut nodes)
+    }
+
+    fn count(&mut self, limit: usize, nodes: &mut Option<usize>) -> Option<usize> {
+        if !self.is_valid {
+            return Some(0);
+        }
+
+        let mut count = 0;
+        self.count_recursive(limit, &mut count, nodes)?;
+        Some(count)
+    }
+
+    /// `None` when the node budget ran out, `nodes` being `None` means there is no budget.
+    fn count_recursive(
+        &mut self,
+        limit: usize,
+        count: &mut usize,
+        nodes: &mut Option<usize>,
+    ) -> Option<()> {
+        if *count >= limit {
+            return Some(());
+        }
+
+        match self.get_next_position() {
+            Some(pos) => {
+                let mut opt = self.get_options(pos);
+                if self.use_randomization {
+                    opt.shuffle(&mut self.rng);
+                }
+                for value in opt {
+                    if let Some(left) = nodes {
+                        *left = left.checked_sub(1)?;
+                    }
+                    let placed = self.place(pos, value);
+                    let searched = match placed {
+                        true => self.count_recursive(limit, count, nodes),
+                        false => Some(()),
+                    };
+                    self.unplace(pos);
+                    searched?;
+                    if *count >= limit {
+                        return Some(());
+                    }
+                }
+            }
+            None => {
+                if self.check_rules() == 2 {
+                    if self.solution.is_none() {
+                        self.solution = Some(self.state.board().clone());
+                    }
+                    *count += 1;
+                }
+            }
+        }
+        Some(())
+    }
+
     pub fn get_solution(&self) -> Option<Board> {
         self.solution.clone()
-    }    
+    }
+
+    pub fn get_attempts(&self) -> usize {
+        self.attempts
+    }
+}
+
+fn touches(dirty: &[bool], side: usize, cells: &[Position]) -> bool {
+    cells.iter().any(|pos| dirty[pos.index(side)])
 }
 
+/// For every digit, the digits allowed next to it by a symmetric relation between two cells.
+fn pair_table(side: usize, allows: impl Fn(usize, usize) -> bool) -> Vec<DigitSet> {
+    (0..=side)
+        .map(|a| {
+            (1..=side)
+                .filter(|&b| allows(a, b))
+                .fold(DigitSet::empty(), |set, b| set.with(b))
+        })
+        .collect()
+}
 
-pub fn generate(mut game: Game) -> Option<Game> {
-    let mut solver = Solver::new(game.clone(), true);
+/// Describes a rule as weighted cells that have to add up to a total.
+fn linear_constraint(rule: &Rule) -> Option<(Vec<(Position, isize)>, isize)> {
+    let total = match rule {
+        Rule::Sum(r) => r.get_sum(),
+        Rule::Cage(r) => r.get_sum()?,
+        Rule::LittleKiller(r) => r.get_sum(),
+        Rule::Arrow(r) => {
+            let circle = r.get_circle();
+            if circle == 0 || r.positions.len() <= circle {
+                return None;
+            }
+
+            let terms = r
+                .positions
+                .iter()
+                .enumerate()
+                .map(|(i, &pos)| match i < circle {
+                    true => (pos, 10isize.pow((circle - 1 - i) as u32)),
+                    false => (pos, -1),
+                })
+                .collect();
+            return Some((terms, 0));
+        }
+        _ => return None,
+    };
+
+    let terms = rule
+        .get_positions()
+        .into_iter()
+        .map(|pos| (pos, 1))
+        .collect();
+    Some((terms, total as isize))
+}
+
+/// Placements a uniqueness check may try while generating before the answer counts as unknown.
+pub const UNIQUENESS_BUDGET: usize = 5_000;
 
+pub fn has_unique_solution(game: &Game) -> bool {
+    Solver::new(game.clone(), false).count_solutions(2) == 1
+}
+
+/// Rating of a puzzle the logical solver cannot finish, so that guessing is needed.
+pub const GUESSING: usize = Technique::SimpleColoring.level() + 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+    Expert,
+    Score(usize),
+}
+
+impl Difficulty {
+    /// Ratings that fall in the band, see `rate`. Easy puzzles need only singles, medium ones
+    /// up to subsets and pointing/claiming, hard ones fish, XY-wings or coloring, and expert
+    /// ones cannot be finished without guessing.
+    pub fn band(&self) -> (usize, usize) {
+        match self {
+            Difficulty::Easy => (0, Technique::HiddenSingle.level()),
+            Difficulty::Medium => (
+                Technique::RuleConflict.level(),
+                Technique::LockedCandidates.level(),
+            ),
+            Difficulty::Hard => (Technique::XWing.level(), Technique::SimpleColoring.level()),
+            Difficulty::Expert => (GUESSING, GUESSING),
+            Difficulty::Score(score) => (*score, *score),
+        }
+    }
+}
+
+impl std::str::FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "easy" => Ok(Difficulty::Easy),
+            "medium" => Ok(Difficulty::Medium),
+            "hard" => Ok(Difficulty::Hard),
+            "expert" => Ok(Difficulty::Expert),
+            other => other
+                .parse()
+                .map(Difficulty::Score)
+                .map_err(|_| format!("unknown difficulty: {}", other)),
+        }
+    }
+}
+
+/// Rates a puzzle by the hardest technique the logical solver needs to finish it, as its
+/// `Technique::level`, or `GUESSING` when the solver gets stuck. An already solved board rates 0.
+/// `None` means the puzzle has no solution.
+pub fn rate(game: &Game) -> Option<usize> {
+    let mut solver = Solver::new(game.clone(), false);
     solver.solve();
     solver.get_solution()?;
 
-    let mut part_board = solver.get_solution().unwrap();
+    let mut logical = LogicalSolver::new(game);
+    if !logical.solve() {
+        return Some(GUESSING);
+    }
+    Some(
+        logical
+            .steps()
+            .iter()
+            .map(|step| step.technique.level())
+            .max()
+            .unwrap_or(0),
+    )
+}
+
+pub fn generate(game: Game) -> Option<Game> {
+    generate_rated(game, None).map(|(game, _)| game)
+}
 
-    let mut positions: Vec<Position> = (1..=solver.n)
-        .flat_map(|row| (1..=solver.n).filter_map(move |col| Position::new(row, col)))
-        .collect();
+pub fn generate_with_difficulty(
+    game: Game,
+    difficulty: Difficulty,
+    max_tries: usize,
+) -> Option<(Game, usize)> {
+    let (low, high) = difficulty.band();
+
+    for _ in 0..max_tries {
+        let (candidate, rating) = generate_rated(game.clone(), Some(high))?;
+        if rating >= low {
+            return Some((candidate, rating));
+        }
+    }
+    None
+}
+
+/// Removes clues while the puzzle stays unique and, with `max_rating`, no harder than it.
+fn generate_rated(mut game: Game, max_rating: Option<usize>) -> Option<(Game, usize)> {
+    let mut solver = Solver::new(game.clone(), true);
+
+    solver.solve();
+    let mut part_board = solver.get_solution()?;
+
+    let mut positions = part_board.cells();
 
     positions.shuffle(&mut solver.rng);
 
-    for pos in positions.into_iter().take(solver.n * solver.n / 4 * 3) {
+    for pos in positions {
+        let value = part_board.get_value(pos);
         part_board.set_value(pos, 0);
+        game.set_board(part_board.clone());
+
+        // a check that runs out of budget cannot promise uniqueness, so the clue stays
+        let solutions =
+            Solver::new(game.clone(), false).count_solutions_within(2, UNIQUENESS_BUDGET);
+        if solutions != Some(1) {
+            part_board.set_value(pos, value);
+            continue;
+        }
+
+        // without a maximum every unique removal is kept and only the final puzzle is rated
+        let Some(max_rating) = max_rating else {
+            continue;
+        };
+        match rate(&game) {
+            Some(new_rating) if new_rating <= max_rating => {}
+            _ => part_board.set_value(pos, value),
+        }
     }
 
     game.set_board(part_board);
-    Some(game)
-}
\ No newline at end of file
+    let rating = rate(&game)?;
+    Some((game, rating))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::board::Corner;
+    use crate::logic::board::Outside;
+    use crate::logic::rules::ArrowRule;
+    use crate::logic::rules::CageRule;
+    use crate::logic::rules::ExpressionRule;
+    use crate::logic::rules::RelationRule;
+    use crate::logic::rules::SandwichRule;
+    use crate::logic::rules::SumRule;
+    use crate::logic::rules::ThermometerRule;
+    use crate::logic::rules::{Diagonal, LittleKillerRule};
+    use crate::logic::rules::{DigitFilter, RestrictionRule};
+    use crate::logic::rules::{KropkiColor, KropkiRule};
+    use crate::logic::rules::{LineKind, LineRule};
+    use crate::logic::rules::{XVMark, XVRule};
+
+    const HARD_9: &str =
+        "4.....8.5.3..........7......2.....6.....8.4......1.......6.3.7.5..2.....1.4......";
+    const HARD_16: &str = "..f...5b.ge...1.......2...73.59.g.9.3..dfc.2.a48.....e.....52..f.1.a.7..g.c..b..7.ce..da......63..46..9e...7..a2...d....68.....4...5a348.79...c...63..cf.1....7..8b2.6.7.....fd.9...db..c..6318.....e..6..5...3..d...279..4.a.......c.8.7e..5.....e......agc.6b7";
+
+    fn parse(puzzle: &str, side: usize, sub_rows: usize, sub_cols: usize) -> Game {
+        let mut game = Game::new(side, sub_rows, sub_cols).unwrap();
+        let mut board = game.board();
+        for (pos, c) in board.cells().into_iter().zip(puzzle.chars()) {
+            board.set_value(pos, c.to_digit(36).unwrap_or(0) as usize);
+        }
+        game.set_board(board);
+        game
+    }
+
+    fn cell(row: usize, col: usize) -> Position {
+        Position::new(row, col, 9).unwrap()
+    }
+
+    /// A solver for a classic board with `rules`, `global_rules` and `values` (row, column,
+    /// digit), after its initial propagation.
+    fn propagated(
+        rules: Vec<Rule>,
+        global_rules: &[GlobalRule],
+        values: &[(usize, usize, usize)],
+    ) -> Solver {
+        let mut game = Game::new(9, 3, 3).unwrap();
+        for rule in rules {
+            game.add_rule(rule);
+        }
+        for &rule in global_rules {
+            game.set_global_rule(rule, true);
+        }
+        for &(row, col, value) in values {
+            game.set_value(cell(row, col), value);
+        }
+        Solver::new(game, false)
+    }
+
+    fn assert_solves(game: Game, use_randomization: bool, max_attempts: usize) {
+        let mut solver = Solver::new(game.clone(), use_randomization);
+        solver.solve();
+        let solution = solver.get_solution().unwrap();
+        assert!(solution
+            .cells()
+            .iter()
+            .all(|&pos| solution.get_value(pos) > 0));
+        assert!(solver.get_attempts() <= max_attempts);
+
+        let mut solved = game;
+        solved.set_board(solution);
+        assert!(matches!(solved.check_rules(), (None, None)));
+    }
+
+    #[test]
+    fn solves_large_boards_within_a_budget() {
+        assert_solves(parse(HARD_16, 16, 4, 4), false, 1000);
+        assert_solves(Game::new(25, 5, 5).unwrap(), false, 2000);
+        assert_solves(Game::new(25, 5, 5).unwrap(), true, 2000);
+    }
+
+    #[test]
+    fn count_solutions_stops_at_the_limit_or_budget() {
+        let empty = Game::new(4, 2, 2).unwrap();
+        assert_eq!(Solver::new(empty.clone(), false).count_solutions(2), 2);
+        assert_eq!(Solver::new(empty.clone(), false).count_solutions(1000), 288);
+        assert_eq!(
+            Solver::new(empty, false).count_solutions_within(1000, 10),
+            None
+        );
+
+        let puzzle = parse(HARD_9, 9, 3, 3);
+        let within = |budget| Solver::new(puzzle.clone(), false).count_solutions_within(2, budget);
+        assert_eq!(within(100_000), Some(1));
+        assert_eq!(within(10), None);
+    }
+
+    #[test]
+    fn generates_unique_16x16_puzzles() {
+        let puzzle = generate(Game::new(16, 4, 4).unwrap()).unwrap();
+        assert!(has_unique_solution(&puzzle));
+    }
+
+    #[test]
+    fn generated_rating_falls_in_the_requested_band() {
+        for difficulty in [
+            Difficulty::Easy,
+            Difficulty::Medium,
+            Difficulty::Hard,
+            Difficulty::Expert,
+        ] {
+            let game = Game::new(9, 3, 3).unwrap();
+            let (puzzle, rating) = generate_with_difficulty(game, difficulty, 20).unwrap();
+            let (low, high) = difficulty.band();
+            assert!(
+                (low..=high).contains(&rating),
+                "{:?} rated {}",
+                difficulty,
+                rating
+            );
+            assert_eq!(rate(&puzzle), Some(rating));
+            assert!(has_unique_solution(&puzzle));
+        }
+    }
+
+    #[test]
+    fn cages_propagate_sums_and_distinct_digits() {
+        let cage = |cells, sum| Rule::Cage(CageRule::new(cells, sum, 27));
+
+        let solver = propagated(vec![cage(vec![cell(1, 1), cell(5, 5)], Some(3))], &[], &[]);
+        assert_eq!(solver.get_options(cell(1, 1)), [1, 2]);
+        assert_eq!(solver.get_options(cell(5, 5)), [1, 2]);
+
+        let solver = propagated(
+            vec![cage(vec![cell(1, 1), cell(5, 5)], None)],
+            &[],
+            &[(1, 1, 4)],
+        );
+        assert!(!solver.get_options(cell(5, 5)).contains(&4));
+    }
+
+    #[test]
+    fn sums_bound_their_cells() {
+        let sum = |cells, total| Rule::Sum(SumRule::new(cells, total, 27));
+
+        let solver = propagated(vec![sum(vec![cell(1, 1), cell(5, 5)], 18)], &[], &[]);
+        assert_eq!(solver.get_options(cell(1, 1)), [9]);
+        assert_eq!(solver.get_options(cell(5, 5)), [9]);
+
+        let row = vec![cell(1, 1), cell(1, 2), cell(1, 3)];
+        let solver = propagated(vec![sum(row, 10)], &[], &[(1, 1, 1)]);
+        assert_eq!(solver.get_options(cell(1, 2)), [2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn relations_chain_their_bounds() {
+        let relation = |a, b| {
+            let mut rule = RelationRule::new(27);
+            rule.positions = vec![a, b];
+            Rule::Relation(rule)
+        };
+        let chain = (1..4)
+            .map(|col| relation(cell(1, col), cell(1, col + 1)))
+            .collect();
+
+        let solver = propagated(chain, &[], &[]);
+        assert_eq!(solver.get_options(cell(1, 1)), [1, 2, 3, 4, 5, 6]);
+        assert_eq!(solver.get_options(cell(1, 4)), [4, 5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn thermometers_leave_room_for_every_bulb_step() {
+        let row = (1..=9).map(|col| cell(1, col)).collect();
+        let solver = propagated(
+            vec![Rule::Thermometer(ThermometerRule::new(row, 27))],
+            &[],
+            &[],
+        );
+        for col in 1..=9 {
+            assert_eq!(solver.get_options(cell(1, col)), [col]);
+        }
+
+        let short = vec![cell(1, 1), cell(2, 1), cell(3, 1)];
+        let solver = propagated(
+            vec![Rule::Thermometer(ThermometerRule::new(short, 27))],
+            &[],
+            &[],
+        );
+        assert_eq!(solver.get_options(cell(2, 1)), [2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn arrows_bound_the_circle_by_the_shaft() {
+        // a two-digit circle fed by two cells can only stand for 11 to 18
+        let cells = vec![cell(1, 1), cell(1, 2), cell(2, 3), cell(3, 3)];
+        let solver = propagated(vec![Rule::Arrow(ArrowRule::new(cells, 2, 27))], &[], &[]);
+        assert_eq!(solver.get_options(cell(1, 1)), [1]);
+        assert!(!solver.get_options(cell(2, 3)).contains(&1));
+
+        let cells = vec![cell(1, 1), cell(1, 2), cell(1, 3)];
+        let solver = propagated(
+            vec![Rule::Arrow(ArrowRule::new(cells, 1, 27))],
+            &[],
+            &[(1, 1, 4)],
+        );
+        assert_eq!(solver.get_options(cell(1, 2)), [1, 2, 3]);
+    }
+
+    #[test]
+    fn kropki_dots_pair_up_digits() {
+        let dot = |color| {
+            let mut rule = KropkiRule::new(color, 27);
+            rule.positions = vec![cell(1, 1), cell(1, 2)];
+            Rule::Kropki(rule)
+        };
+
+        let solver = propagated(vec![dot(KropkiColor::White)], &[], &[(1, 1, 3)]);
+        assert_eq!(solver.get_options(cell(1, 2)), [2, 4]);
+        let solver = propagated(vec![dot(KropkiColor::Black)], &[], &[(1, 1, 3)]);
+        assert_eq!(solver.get_options(cell(1, 2)), [6]);
+
+        let negative = [GlobalRule::KropkiNegative];
+        let solver = propagated(vec![], &negative, &[(1, 1, 3)]);
+        assert_eq!(solver.get_options(cell(1, 2)), [1, 5, 7, 8, 9]);
+        let solver = propagated(vec![dot(KropkiColor::White)], &negative, &[(1, 1, 3)]);
+        assert_eq!(solver.get_options(cell(1, 2)), [2, 4]);
+    }
+
+    #[test]
+    fn xv_marks_complete_the_sum() {
+        let mark = |mark| {
+            let mut rule = XVRule::new(mark, 27);
+            rule.positions = vec![cell(1, 1), cell(2, 1)];
+            Rule::XV(rule)
+        };
+
+        let solver = propagated(vec![mark(XVMark::X)], &[], &[(1, 1, 3)]);
+        assert_eq!(solver.get_options(cell(2, 1)), [7]);
+        let solver = propagated(vec![mark(XVMark::V)], &[], &[(1, 1, 3)]);
+        assert_eq!(solver.get_options(cell(2, 1)), [2]);
+
+        let solver = propagated(vec![], &[GlobalRule::XVNegative], &[(1, 1, 3)]);
+        assert_eq!(solver.get_options(cell(2, 1)), [1, 4, 5, 6, 8, 9]);
+    }
+
+    #[test]
+    fn chess_moves_become_peers() {
+        let solver = propagated(vec![], &[GlobalRule::AntiKnight], &[(5, 5, 5)]);
+        assert!(!solver.get_options(cell(3, 4)).contains(&5));
+        assert!(!solver.get_options(cell(7, 6)).contains(&5));
+        assert!(solver.get_options(cell(3, 3)).contains(&5));
+
+        let solver = propagated(vec![], &[GlobalRule::AntiKing], &[(3, 3, 5)]);
+        assert!(!solver.get_options(cell(4, 4)).contains(&5));
+        assert!(solver.get_options(cell(5, 5)).contains(&5));
+    }
+
+    #[test]
+    fn restrictions_narrow_the_candidates() {
+        let only = |filter| {
+            let rule = RestrictionRule::new(vec![cell(1, 1)], filter, 27);
+            propagated(vec![Rule::Restriction(rule)], &[], &[]).get_options(cell(1, 1))
+        };
+        assert_eq!(only(DigitFilter::Even), [2, 4, 6, 8]);
+        assert_eq!(only(DigitFilter::High), [6, 7, 8, 9]);
+        assert_eq!(only(DigitFilter::Digits(vec![5])), [5]);
+    }
+
+    #[test]
+    fn little_killers_bound_their_diagonal() {
+        let clue = Outside::new(0, 7, 9).unwrap();
+        let rule = LittleKillerRule::new(clue, Diagonal::DownRight, 17, 9, 27);
+        let solver = propagated(vec![Rule::LittleKiller(rule)], &[], &[]);
+        assert_eq!(solver.get_options(cell(1, 8)), [8, 9]);
+        assert_eq!(solver.get_options(cell(2, 9)), [8, 9]);
+    }
+
+    #[test]
+    fn sandwiches_keep_the_crust_off_the_middle() {
+        // 35 needs at least five fillings, so rows 4 to 6 always lie between 1 and 9
+        let clue = Outside::new(0, 1, 9).unwrap();
+        let rule = SandwichRule::new(clue, 35, 9, 27);
+        let solver = propagated(vec![Rule::Sandwich(rule)], &[], &[]);
+        for row in 4..=6 {
+            assert_eq!(solver.get_options(cell(row, 1)), [2, 3, 4, 5, 6, 7, 8]);
+        }
+        assert_eq!(solver.get_options(cell(3, 1)).len(), 9);
+    }
+
+    #[test]
+    fn lines_propagate_by_kind() {
+        let line = |kind, cells: &[(usize, usize)], value| {
+            let cells = cells.iter().map(|&(row, col)| cell(row, col)).collect();
+            let rule = Rule::Line(LineRule::new(cells, kind, 27));
+            propagated(vec![rule], &[], &[value])
+        };
+        let diagonal = [(3, 3), (4, 4), (5, 5)];
+
+        let solver = line(LineKind::Palindrome, &diagonal, (3, 3, 7));
+        assert_eq!(solver.get_options(cell(5, 5)), [7]);
+
+        let solver = line(LineKind::Whispers, &diagonal[..2], (3, 3, 4));
+        assert_eq!(solver.get_options(cell(4, 4)), [9]);
+
+        let solver = line(LineKind::Renban, &[(1, 1), (1, 2), (1, 3)], (1, 1, 1));
+        assert_eq!(solver.get_options(cell(1, 2)), [2, 3]);
+
+        // each box the line crosses holds a segment of one cell
+        let solver = line(LineKind::RegionSum, &diagonal[..2], (3, 3, 5));
+        assert_eq!(solver.get_options(cell(4, 4)), [5]);
+    }
+
+    #[test]
+    fn non_consecutive_neighbours_drop_adjacent_digits() {
+        let solver = propagated(vec![], &[GlobalRule::NonConsecutive], &[(5, 5, 5)]);
+        assert_eq!(solver.get_options(cell(5, 6)), [1, 2, 3, 7, 8, 9]);
+        assert!(solver.get_options(cell(6, 6)).contains(&4));
+    }
+
+    #[test]
+    fn quads_place_their_digits_around_the_corner() {
+        let quad = |digits| Rule::Quad(QuadRule::new(Corner::new(1, 1, 9).unwrap(), digits, 27));
+
+        let solver = propagated(vec![quad(vec![1, 2, 3, 4])], &[], &[]);
+        assert_eq!(solver.get_options(cell(2, 2)), [1, 2, 3, 4]);
+
+        let solver = propagated(vec![quad(vec![7])], &[], &[(1, 1, 1), (1, 2, 2), (2, 1, 3)]);
+        assert_eq!(solver.get_options(cell(2, 2)), [7]);
+    }
+
+    #[test]
+    fn expressions_narrow_their_cells() {
+        let expression = |source| Rule::Expression(ExpressionRule::new(source, 27).unwrap());
+
+        let solver = propagated(vec![expression("r1c1 + r5c5 == 17")], &[], &[]);
+        assert_eq!(solver.get_options(cell(1, 1)), [8, 9]);
+
+        let solver = propagated(vec![expression("abs(r1c1 - r1c2) > 7")], &[], &[]);
+        assert_eq!(solver.get_options(cell(1, 2)), [1, 9]);
+
+        let solver = propagated(vec![expression("distinct(r1c1, r5c5)")], &[], &[(1, 1, 3)]);
+        assert!(!solver.get_options(cell(5, 5)).contains(&3));
+    }
+}
@@ -3,7 +3,7 @@ macro_rules! for_pos {
     ($N:expr, $body:expr) => {
         for col in 1..=$N {
             for row in 1..=$N {
-                let pos = Position::new(row, col).unwrap();
+                let pos = Position::new(row, col, $N).unwrap();
                 $body(pos);
             }
         }
@@ -1,8 +1,8 @@
 mod gui;
-mod logic;
 use gui::app::App;
 use relm4::RelmApp;
 use std::env;
+use sudoku_solver_and_generator::logic;
 #[macro_use]
 mod macros;
 